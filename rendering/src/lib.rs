@@ -5,19 +5,53 @@ mod tests {
     use crate::raytracer::actor::RayTraceable;
     use crate::raytracer::actor::Sphere;
     use crate::raytracer::camera::Camera;
+    use crate::raytracer::canvas::tiles;
+    use crate::raytracer::canvas::Adaptive;
+    use crate::raytracer::canvas::CancelToken;
     use crate::raytracer::canvas::Canvas;
+    use crate::raytracer::canvas::Checkpoint;
+    use crate::raytracer::canvas::Progress;
+    use crate::raytracer::canvas::RenderOptions;
+    use crate::raytracer::canvas::Tile;
+    use crate::raytracer::canvas::TileOrder;
+    use crate::raytracer::common_testing::diffuse_sphere_scene;
     use crate::raytracer::common_testing::equals_to_baseline;
+    use crate::raytracer::common_testing::flat_sphere_scene;
     use crate::raytracer::common_testing::init_image_testing;
+    use crate::raytracer::common_testing::normals_sphere_scene;
+    use crate::raytracer::film::Film;
+    use crate::raytracer::filter::Filter;
+    use crate::raytracer::filter::FilterKind;
+    use crate::raytracer::image::Image;
+    use crate::raytracer::integrator::AmbientOcclusion;
+    use crate::raytracer::integrator::Metropolis;
+    use crate::raytracer::integrator::PathTracer;
+    use crate::raytracer::integrator::PhotonMapper;
+    use crate::raytracer::material::Conductor;
+    use crate::raytracer::material::Cutout;
     use crate::raytracer::material::Dielectric;
+    use crate::raytracer::material::Dispersion;
     use crate::raytracer::material::Lambertian;
+    use crate::raytracer::material::Layered;
+    use crate::raytracer::material::MaterialLibrary;
     use crate::raytracer::material::Metal;
+    use crate::raytracer::material::NormalDetail;
+    use crate::raytracer::material::NormalMapped;
     use crate::raytracer::material::Primary;
+    use crate::raytracer::material::Principled;
     use crate::raytracer::material::Shading;
+    use crate::raytracer::material::Subsurface;
+    use crate::raytracer::material::ThinFilm;
+    use crate::raytracer::sampler::Sampling;
     use crate::raytracer::scenes;
+    use crate::raytracer::texture::CheckerTexture;
     use ndarray::arr1;
 
     extern crate image;
     use image::RgbaImage;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
     #[test]
     fn render_background() {
@@ -35,7 +69,14 @@ mod tests {
             0.0,
         );
 
-        let mut canvas = Canvas::new(dims[0], dims[1], vec![], 1, camera);
+        let mut canvas = Canvas::new(
+            dims[0],
+            dims[1],
+            vec![],
+            MaterialLibrary::new(),
+            1,
+            camera,
+        );
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -50,14 +91,18 @@ mod tests {
         let mut output_path = init_image_testing();
         output_path.push("render_sphere_color.png");
 
+        let mut materials = MaterialLibrary::new();
         let mut actors = vec![];
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Primary::new(
-                arr1(&[1.0, 0.0, 0.0, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "primary",
+                Box::new(Primary::new(
+                    arr1(&[1.0, 0.0, 0.0, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         let dims: [u32; 2] = [200, 100];
@@ -70,7 +115,8 @@ mod tests {
             arr1(&[0.0, 1.0, 0.0, 0.0]),
             0.0,
         );
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 1, camera);
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 1, camera);
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -85,14 +131,18 @@ mod tests {
         let mut output_path = init_image_testing();
         output_path.push("render_sphere_normals.png");
 
+        let mut materials = MaterialLibrary::new();
         let mut actors = vec![];
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Primary::new(
-                arr1(&[1.0, 0.0, 0.0, 1.0]),
-                Shading::NORMALS,
-            )),
+            material: materials.add(
+                "primary",
+                Box::new(Primary::new(
+                    arr1(&[1.0, 0.0, 0.0, 1.0]),
+                    Shading::NORMALS,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         let dims: [u32; 2] = [200, 100];
@@ -105,7 +155,8 @@ mod tests {
             arr1(&[0.0, 1.0, 0.0, 0.0]),
             0.0,
         );
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 1, camera);
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 1, camera);
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -120,36 +171,29 @@ mod tests {
         let mut output_path = init_image_testing();
         output_path.push("render_antialiasing.png");
 
-        let mut actors = vec![];
-        actors.push(Box::new(Sphere {
-            center: arr1(&[0.0, 0.0, -1.0, 1.0]),
-            radius: 0.5,
-            material: Box::new(Primary::new(
-                arr1(&[1.0, 0.0, 0.0, 1.0]),
-                Shading::NORMALS,
-            )),
-        }) as Box<dyn RayTraceable>);
+        let dims: [u32; 2] = [200, 100];
+        let mut canvas = normals_sphere_scene(dims[0], dims[1], 50);
+        canvas.render_scene_rayon();
+        let image = canvas.grab_frame().as_flat_vec_u8();
+        let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
+        let _result = image_png.save(output_path.clone());
 
-        actors.push(Box::new(Sphere {
-            center: arr1(&[0.0, -100.5, -1.0, 1.0]),
-            radius: 100.0,
-            material: Box::new(Primary::new(
-                arr1(&[0.5, 0.5, 0.5, 1.0]),
-                Shading::COLOR,
-            )),
-        }) as Box<dyn RayTraceable>);
+        let test_image = canvas.grab_frame();
+        equals_to_baseline(test_image, output_path, 2.0);
+    }
+
+    #[test]
+    fn render_antialiasing_mitchell() {
+        let mut output_path = init_image_testing();
+        output_path.push("render_antialiasing_mitchell.png");
 
         let dims: [u32; 2] = [200, 100];
-        let camera = Camera::new(
-            90.0,
-            dims[0],
-            dims[1],
-            arr1(&[0.0, 0.0, 0.0, 1.0]),
-            arr1(&[0.0, 0.0, -1.0, 1.0]),
-            arr1(&[0.0, 1.0, 0.0, 0.0]),
-            0.0,
-        );
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 50, camera);
+        let mut canvas = normals_sphere_scene(dims[0], dims[1], 50);
+        let mitchell = Filter::new(FilterKind::Mitchell {
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        });
+        canvas.filter = mitchell;
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -157,6 +201,80 @@ mod tests {
 
         let test_image = canvas.grab_frame();
         equals_to_baseline(test_image, output_path, 2.0);
+
+        // For the same radius, the negative lobes of Mitchell keep more of
+        // the largest step across the left of the silhouette (sky to
+        // sphere, in red) than the tent and Gaussian filters.
+        let contrast = |film: &Film| {
+            (25..50)
+                .map(|y| {
+                    (60..95)
+                        .map(|x| {
+                            film.get_value(x, y, 0)
+                                - film.get_value(x + 1, y, 0)
+                        })
+                        .fold(0.0, f64::max)
+                })
+                .sum::<f64>()
+        };
+        let sharp = contrast(&canvas.grab_film());
+        let smooth = [FilterKind::Tent, FilterKind::Gaussian { alpha: 2.0 }];
+        for kind in smooth.iter() {
+            canvas.filter = Filter {
+                kind: *kind,
+                radius: mitchell.radius,
+            };
+            canvas.render_scene_rayon();
+            assert!(sharp > 1.05 * contrast(&canvas.grab_film()));
+        }
+    }
+
+    #[test]
+    fn render_filters() {
+        // Inside a sphere of a single color, every filter gives it back.
+        let kinds = [
+            FilterKind::Box,
+            FilterKind::Tent,
+            FilterKind::Gaussian { alpha: 2.0 },
+            FilterKind::Mitchell {
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            FilterKind::Lanczos { tau: 3.0 },
+        ];
+        for kind in kinds.iter() {
+            let mut canvas =
+                flat_sphere_scene(20, 10, 4, arr1(&[0.3, 0.3, 0.3, 1.0]));
+            canvas.filter = Filter::new(*kind);
+            canvas.render_scene_rayon();
+            for pixel in canvas.grab_frame().data.iter() {
+                assert!(pixel.data == [149, 149, 149, 255]);
+            }
+        }
+    }
+
+    #[test]
+    fn render_film() {
+        // Radiance above 1.0 is kept in the film, and clamped in the frame.
+        let mut canvas =
+            flat_sphere_scene(20, 10, 4, arr1(&[3.0, 0.5, 0.0, 1.0]));
+        canvas.render_scene_rayon();
+
+        let film = canvas.grab_film();
+        assert!(film.width == 20 && film.height == 10);
+        for pixel in film.data.iter() {
+            assert!((pixel.data[0] - 3.0).abs() < 1e-9);
+            assert!((pixel.data[1] - 0.5).abs() < 1e-9);
+        }
+        for pixel in canvas.grab_frame().data.iter() {
+            assert!(pixel.data == [255, 188, 0, 255]);
+        }
+
+        // Frames are developed from the film, without rendering again.
+        canvas.tone_mapper.exposure = -2.0;
+        for pixel in canvas.grab_frame().data.iter() {
+            assert!(pixel.data == [225, 99, 0, 255]);
+        }
     }
 
     #[test]
@@ -164,23 +282,30 @@ mod tests {
         let mut output_path = init_image_testing();
         output_path.push("render_diffuse.png");
 
+        let mut materials = MaterialLibrary::new();
         let mut actors = vec![];
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[1.0, 0.0, 0.0, 1.0]),
-                Shading::NORMALS,
-            )),
+            material: materials.add(
+                "diffuse",
+                Box::new(Lambertian::new(
+                    arr1(&[1.0, 0.0, 0.0, 1.0]),
+                    Shading::NORMALS,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, -100.5, -1.0, 1.0]),
             radius: 100.0,
-            material: Box::new(Lambertian::new(
-                arr1(&[1.0, 0.0, 0.0, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "ground",
+                Box::new(Lambertian::new(
+                    arr1(&[1.0, 0.0, 0.0, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         let dims: [u32; 2] = [200, 100];
@@ -194,7 +319,8 @@ mod tests {
             0.0,
         );
 
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 100, camera);
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 100, camera);
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -209,42 +335,55 @@ mod tests {
         let mut output_path = init_image_testing();
         output_path.push("camera_positionable.png");
 
+        let mut materials = MaterialLibrary::new();
         let mut actors = vec![];
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, -100.5, -1.0, 1.0]),
             radius: 100.0,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.6, 0.6, 0.6, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "ground",
+                Box::new(Lambertian::new(
+                    arr1(&[0.6, 0.6, 0.6, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.1, 0.2, 0.5, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse",
+                Box::new(Lambertian::new(
+                    arr1(&[0.1, 0.2, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[1.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[1.0, 0.4, 0.4, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse_2",
+                Box::new(Lambertian::new(
+                    arr1(&[1.0, 0.4, 0.4, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[-1.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.1, 0.8, 0.2, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse_3",
+                Box::new(Lambertian::new(
+                    arr1(&[0.1, 0.8, 0.2, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         let dims: [u32; 2] = [200, 100];
@@ -258,7 +397,8 @@ mod tests {
             0.0,
         );
 
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 50, camera);
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 50, camera);
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -275,23 +415,30 @@ mod tests {
 
         let rad = std::f64::consts::PI / 4.0;
 
+        let mut materials = MaterialLibrary::new();
         let mut actors = vec![];
         actors.push(Box::new(Sphere {
             center: arr1(&[-rad, 0.0, -1.0, 1.0]),
             radius: rad,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.0, 0.0, 1.0, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse",
+                Box::new(Lambertian::new(
+                    arr1(&[0.0, 0.0, 1.0, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[rad, 0.0, -1.0, 1.0]),
             radius: rad,
-            material: Box::new(Lambertian::new(
-                arr1(&[1.0, 0.0, 0.0, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse_2",
+                Box::new(Lambertian::new(
+                    arr1(&[1.0, 0.0, 0.0, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         let dims: [u32; 2] = [200, 100];
@@ -305,7 +452,8 @@ mod tests {
             0.0,
         );
 
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 50, camera);
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 50, camera);
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -320,44 +468,57 @@ mod tests {
         let mut output_path = init_image_testing();
         output_path.push("render_metal.png");
 
+        let mut materials = MaterialLibrary::new();
         let mut actors = vec![];
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, -100.5, -1.0, 1.0]),
             radius: 100.0,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.6, 0.6, 0.6, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "ground",
+                Box::new(Lambertian::new(
+                    arr1(&[0.6, 0.6, 0.6, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.1, 0.2, 0.5, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse",
+                Box::new(Lambertian::new(
+                    arr1(&[0.1, 0.2, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[1.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Metal::new(
-                arr1(&[1.0, 0.4, 0.4, 1.0]),
-                Shading::COLOR,
-                0.8,
-            )),
+            material: materials.add(
+                "metal",
+                Box::new(Metal::new(
+                    arr1(&[1.0, 0.4, 0.4, 1.0]),
+                    Shading::COLOR,
+                    0.8,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[-1.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Metal::new(
-                arr1(&[0.8, 0.8, 0.8, 1.0]),
-                Shading::COLOR,
-                0.0,
-            )),
+            material: materials.add(
+                "metal_2",
+                Box::new(Metal::new(
+                    arr1(&[0.8, 0.8, 0.8, 1.0]),
+                    Shading::COLOR,
+                    0.0,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         let dims: [u32; 2] = [200, 100];
@@ -371,7 +532,8 @@ mod tests {
             0.0,
         );
 
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 50, camera);
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 50, camera);
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -386,53 +548,69 @@ mod tests {
         let mut output_path = init_image_testing();
         output_path.push("render_dielectric.png");
 
+        let mut materials = MaterialLibrary::new();
         let mut actors = vec![];
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, -100.5, -1.0, 1.0]),
             radius: 100.0,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.8, 0.8, 0.0, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "ground",
+                Box::new(Lambertian::new(
+                    arr1(&[0.8, 0.8, 0.0, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.1, 0.2, 0.5, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse",
+                Box::new(Lambertian::new(
+                    arr1(&[0.1, 0.2, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.1, 0.2, 0.5, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse_2",
+                Box::new(Lambertian::new(
+                    arr1(&[0.1, 0.2, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[1.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Metal::new(
-                arr1(&[0.8, 0.6, 0.2, 1.0]),
-                Shading::COLOR,
-                0.0,
-            )),
+            material: materials.add(
+                "metal",
+                Box::new(Metal::new(
+                    arr1(&[0.8, 0.6, 0.2, 1.0]),
+                    Shading::COLOR,
+                    0.0,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[-1.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Dielectric::new(
-                arr1(&[1.0, 1.0, 1.0, 0.0]),
-                Shading::COLOR,
-                1.5,
-            )),
+            material: materials.add(
+                "glass",
+                Box::new(Dielectric::new(
+                    arr1(&[1.0, 1.0, 1.0, 0.0]),
+                    Shading::COLOR,
+                    1.5,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         let dims: [u32; 2] = [200, 100];
@@ -446,7 +624,8 @@ mod tests {
             0.0,
         );
 
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 100, camera);
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 100, camera);
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -461,63 +640,82 @@ mod tests {
         let mut output_path = init_image_testing();
         output_path.push("render_dielectric_bubble.png");
 
+        let mut materials = MaterialLibrary::new();
         let mut actors = vec![];
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, -100.5, -1.0, 1.0]),
             radius: 100.0,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.8, 0.8, 0.0, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "ground",
+                Box::new(Lambertian::new(
+                    arr1(&[0.8, 0.8, 0.0, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.1, 0.2, 0.5, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse",
+                Box::new(Lambertian::new(
+                    arr1(&[0.1, 0.2, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.1, 0.2, 0.5, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse_2",
+                Box::new(Lambertian::new(
+                    arr1(&[0.1, 0.2, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[1.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Metal::new(
-                arr1(&[0.8, 0.6, 0.2, 1.0]),
-                Shading::COLOR,
-                0.0,
-            )),
+            material: materials.add(
+                "metal",
+                Box::new(Metal::new(
+                    arr1(&[0.8, 0.6, 0.2, 1.0]),
+                    Shading::COLOR,
+                    0.0,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[-1.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Dielectric::new(
-                arr1(&[1.0, 1.0, 1.0, 0.0]),
-                Shading::COLOR,
-                1.5,
-            )),
+            material: materials.add(
+                "glass",
+                Box::new(Dielectric::new(
+                    arr1(&[1.0, 1.0, 1.0, 0.0]),
+                    Shading::COLOR,
+                    1.5,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[-1.0, 0.0, -1.0, 1.0]),
             radius: -0.45,
-            material: Box::new(Dielectric::new(
-                arr1(&[1.0, 1.0, 1.0, 0.0]),
-                Shading::COLOR,
-                1.5,
-            )),
+            material: materials.add(
+                "glass_2",
+                Box::new(Dielectric::new(
+                    arr1(&[1.0, 1.0, 1.0, 0.0]),
+                    Shading::COLOR,
+                    1.5,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         let dims: [u32; 2] = [200, 100];
@@ -531,7 +729,8 @@ mod tests {
             0.0,
         );
 
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 100, camera);
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 100, camera);
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -546,63 +745,82 @@ mod tests {
         let mut output_path = init_image_testing();
         output_path.push("render_depth_of_field.png");
 
+        let mut materials = MaterialLibrary::new();
         let mut actors = vec![];
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, -100.5, -1.0, 1.0]),
             radius: 100.0,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.8, 0.8, 0.0, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "ground",
+                Box::new(Lambertian::new(
+                    arr1(&[0.8, 0.8, 0.0, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.1, 0.2, 0.5, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse",
+                Box::new(Lambertian::new(
+                    arr1(&[0.1, 0.2, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[0.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Lambertian::new(
-                arr1(&[0.1, 0.2, 0.5, 1.0]),
-                Shading::COLOR,
-            )),
+            material: materials.add(
+                "diffuse_2",
+                Box::new(Lambertian::new(
+                    arr1(&[0.1, 0.2, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[1.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Metal::new(
-                arr1(&[0.8, 0.6, 0.2, 1.0]),
-                Shading::COLOR,
-                0.0,
-            )),
+            material: materials.add(
+                "metal",
+                Box::new(Metal::new(
+                    arr1(&[0.8, 0.6, 0.2, 1.0]),
+                    Shading::COLOR,
+                    0.0,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[-1.0, 0.0, -1.0, 1.0]),
             radius: 0.5,
-            material: Box::new(Dielectric::new(
-                arr1(&[1.0, 1.0, 1.0, 0.0]),
-                Shading::COLOR,
-                1.5,
-            )),
+            material: materials.add(
+                "glass",
+                Box::new(Dielectric::new(
+                    arr1(&[1.0, 1.0, 1.0, 0.0]),
+                    Shading::COLOR,
+                    1.5,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         actors.push(Box::new(Sphere {
             center: arr1(&[-1.0, 0.0, -1.0, 1.0]),
             radius: -0.45,
-            material: Box::new(Dielectric::new(
-                arr1(&[1.0, 1.0, 1.0, 0.0]),
-                Shading::COLOR,
-                1.5,
-            )),
+            material: materials.add(
+                "glass_2",
+                Box::new(Dielectric::new(
+                    arr1(&[1.0, 1.0, 1.0, 0.0]),
+                    Shading::COLOR,
+                    1.5,
+                )),
+            ),
         }) as Box<dyn RayTraceable>);
 
         let dims: [u32; 2] = [200, 100];
@@ -616,7 +834,8 @@ mod tests {
             2.0,
         );
 
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 100, camera);
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 100, camera);
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
@@ -626,12 +845,1103 @@ mod tests {
         equals_to_baseline(test_image, output_path, 26.0);
     }
 
+    #[test]
+    fn render_principled() {
+        let mut output_path = init_image_testing();
+        output_path.push("render_principled.png");
+
+        let mut materials = MaterialLibrary::new();
+        let mut actors = vec![];
+
+        actors.push(Box::new(Sphere {
+            center: arr1(&[0.0, -100.5, -1.0, 1.0]),
+            radius: 100.0,
+            material: materials.add(
+                "ground",
+                Box::new(Principled {
+                    sheen: 1.0,
+                    ..Principled::new(
+                        arr1(&[0.6, 0.6, 0.6, 1.0]),
+                        Shading::COLOR,
+                    )
+                }),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        actors.push(Box::new(Sphere {
+            center: arr1(&[0.0, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "principled",
+                Box::new(Principled {
+                    roughness: 0.2,
+                    clearcoat: 1.0,
+                    ..Principled::new(
+                        arr1(&[0.7, 0.1, 0.1, 1.0]),
+                        Shading::COLOR,
+                    )
+                }),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        actors.push(Box::new(Sphere {
+            center: arr1(&[1.0, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "principled_2",
+                Box::new(Principled {
+                    metallic: 1.0,
+                    roughness: 0.1,
+                    ..Principled::new(
+                        arr1(&[1.0, 0.8, 0.3, 1.0]),
+                        Shading::COLOR,
+                    )
+                }),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        actors.push(Box::new(Sphere {
+            center: arr1(&[-1.0, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "principled_3",
+                Box::new(Principled {
+                    transmission: 1.0,
+                    ..Principled::new(
+                        arr1(&[0.9, 1.0, 0.9, 1.0]),
+                        Shading::COLOR,
+                    )
+                }),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        let dims: [u32; 2] = [200, 100];
+        let camera = Camera::new(
+            90.0,
+            dims[0],
+            dims[1],
+            arr1(&[0.0, 0.0, 0.0, 1.0]),
+            arr1(&[0.0, 0.0, -1.0, 1.0]),
+            arr1(&[0.0, 1.0, 0.0, 0.0]),
+            0.0,
+        );
+
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 100, camera);
+        canvas.render_scene_rayon();
+        let image = canvas.grab_frame().as_flat_vec_u8();
+        let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
+        let _result = image_png.save(output_path.clone());
+
+        let test_image = canvas.grab_frame();
+        equals_to_baseline(test_image, output_path, 28.0);
+    }
+
+    #[test]
+    fn render_spectral_dispersion() {
+        let mut output_path = init_image_testing();
+        output_path.push("render_spectral_dispersion.png");
+
+        let mut materials = MaterialLibrary::new();
+        let mut actors = vec![];
+
+        actors.push(Box::new(Sphere {
+            center: arr1(&[0.6, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "diffuse",
+                Box::new(Lambertian::new(
+                    arr1(&[0.1, 0.2, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        // Exaggerated dispersion, so that the refracted image splits.
+        actors.push(Box::new(Sphere {
+            center: arr1(&[-0.4, 0.0, -0.8, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "glass",
+                Box::new(Dielectric {
+                    dispersion: Some(Dispersion::Cauchy { a: 1.4, b: 0.04 }),
+                    ..Dielectric::new(
+                        arr1(&[1.0, 1.0, 1.0, 1.0]),
+                        Shading::COLOR,
+                        1.5,
+                    )
+                }),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        let dims: [u32; 2] = [200, 100];
+        let camera = Camera::new(
+            90.0,
+            dims[0],
+            dims[1],
+            arr1(&[0.0, 0.0, 0.0, 1.0]),
+            arr1(&[0.0, 0.0, -1.0, 1.0]),
+            arr1(&[0.0, 1.0, 0.0, 0.0]),
+            0.0,
+        );
+
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 100, camera);
+        canvas.spectral = true;
+        canvas.render_scene_rayon();
+        let image = canvas.grab_frame().as_flat_vec_u8();
+        let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
+        let _result = image_png.save(output_path.clone());
+
+        let test_image = canvas.grab_frame();
+        equals_to_baseline(test_image, output_path, 40.0);
+    }
+
+    #[test]
+    fn render_thin_film() {
+        let mut output_path = init_image_testing();
+        output_path.push("render_thin_film.png");
+
+        let mut materials = MaterialLibrary::new();
+        let mut actors = vec![];
+
+        actors.push(Box::new(Sphere {
+            center: arr1(&[0.0, -100.5, -1.0, 1.0]),
+            radius: 100.0,
+            material: materials.add(
+                "ground",
+                Box::new(Lambertian::new(
+                    arr1(&[0.6, 0.6, 0.6, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        // Car paint, clearcoat on top of a diffuse base.
+        actors.push(Box::new(Sphere {
+            center: arr1(&[0.0, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "car_paint",
+                Box::new(Layered::new(
+                    Box::new(Lambertian::new(
+                        arr1(&[0.6, 0.05, 0.05, 1.0]),
+                        Shading::COLOR,
+                    )),
+                    1.5,
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        // Heat-tinted steel, oxide layer on top of iron.
+        let mut steel =
+            Conductor::from_preset("fe", Shading::COLOR, 0.0).unwrap();
+        steel.thin_film = Some(ThinFilm {
+            thickness: 150.0,
+            refraction_idx: 2.4,
+        });
+        actors.push(Box::new(Sphere {
+            center: arr1(&[1.0, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add("steel", Box::new(steel)),
+        }) as Box<dyn RayTraceable>);
+
+        // Soap bubble, a film of water with air on both sides.
+        actors.push(Box::new(Sphere {
+            center: arr1(&[-1.0, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "soap_bubble",
+                Box::new(Dielectric {
+                    thin_film: Some(ThinFilm {
+                        thickness: 400.0,
+                        refraction_idx: 1.33,
+                    }),
+                    ..Dielectric::new(
+                        arr1(&[1.0, 1.0, 1.0, 1.0]),
+                        Shading::COLOR,
+                        1.0,
+                    )
+                }),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        let dims: [u32; 2] = [200, 100];
+        let camera = Camera::new(
+            90.0,
+            dims[0],
+            dims[1],
+            arr1(&[0.0, 0.0, 0.0, 1.0]),
+            arr1(&[0.0, 0.0, -1.0, 1.0]),
+            arr1(&[0.0, 1.0, 0.0, 0.0]),
+            0.0,
+        );
+
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 100, camera);
+        canvas.render_scene_rayon();
+        let image = canvas.grab_frame().as_flat_vec_u8();
+        let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
+        let _result = image_png.save(output_path.clone());
+
+        let test_image = canvas.grab_frame();
+        equals_to_baseline(test_image, output_path, 28.0);
+    }
+
+    #[test]
+    fn render_normal_mapping() {
+        let mut output_path = init_image_testing();
+        output_path.push("render_normal_mapping.png");
+
+        let mut materials = MaterialLibrary::new();
+        let mut actors = vec![];
+
+        // Normal map, squares tilted towards the tangent and the bitangent.
+        actors.push(Box::new(Sphere {
+            center: arr1(&[-0.55, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "normal_map",
+                Box::new(NormalMapped::new(
+                    Box::new(Primary::new(
+                        arr1(&[1.0, 1.0, 1.0, 1.0]),
+                        Shading::NORMALS,
+                    )),
+                    NormalDetail::NormalMap(Box::new(CheckerTexture::new(
+                        arr1(&[0.8, 0.5, 0.8, 1.0]),
+                        arr1(&[0.5, 0.2, 0.8, 1.0]),
+                        8.0,
+                    ))),
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        // Bump map, the edges of the squares show as grooves.
+        actors.push(Box::new(Sphere {
+            center: arr1(&[0.55, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "bump_map",
+                Box::new(NormalMapped::new(
+                    Box::new(Primary::new(
+                        arr1(&[1.0, 1.0, 1.0, 1.0]),
+                        Shading::NORMALS,
+                    )),
+                    NormalDetail::BumpMap {
+                        height: Box::new(CheckerTexture::new(
+                            arr1(&[0.0, 0.0, 0.0, 1.0]),
+                            arr1(&[1.0, 1.0, 1.0, 1.0]),
+                            8.0,
+                        )),
+                        scale: 0.002,
+                    },
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        let dims: [u32; 2] = [200, 100];
+        let camera = Camera::new(
+            90.0,
+            dims[0],
+            dims[1],
+            arr1(&[0.0, 0.0, 0.0, 1.0]),
+            arr1(&[0.0, 0.0, -1.0, 1.0]),
+            arr1(&[0.0, 1.0, 0.0, 0.0]),
+            0.0,
+        );
+
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 1, camera);
+        canvas.render_scene_rayon();
+        let image = canvas.grab_frame().as_flat_vec_u8();
+        let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
+        let _result = image_png.save(output_path.clone());
+
+        let test_image = canvas.grab_frame();
+        equals_to_baseline(test_image, output_path, 5.0);
+    }
+
+    #[test]
+    fn render_subsurface() {
+        let mut output_path = init_image_testing();
+        output_path.push("render_subsurface.png");
+
+        let mut materials = MaterialLibrary::new();
+        let mut actors = vec![];
+
+        actors.push(Box::new(Sphere {
+            center: arr1(&[0.0, -100.5, -1.0, 1.0]),
+            radius: 100.0,
+            material: materials.add(
+                "ground",
+                Box::new(Lambertian::new(
+                    arr1(&[0.6, 0.6, 0.6, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        // Skin, red light travels further.
+        actors.push(Box::new(Sphere {
+            center: arr1(&[-1.0, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "skin",
+                Box::new(Subsurface::new(
+                    arr1(&[0.95, 0.8, 0.7, 1.0]),
+                    arr1(&[0.12, 0.1, 0.08, 1.0]),
+                    1.4,
+                    Shading::COLOR,
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        // Marble, dense and barely absorbing.
+        actors.push(Box::new(Sphere {
+            center: arr1(&[0.0, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "marble",
+                Box::new(Subsurface::new(
+                    arr1(&[0.99, 0.99, 0.98, 1.0]),
+                    arr1(&[0.06, 0.06, 0.06, 1.0]),
+                    1.5,
+                    Shading::COLOR,
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        // Jade, sparse and absorbing red and blue.
+        actors.push(Box::new(Sphere {
+            center: arr1(&[1.0, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "jade",
+                Box::new(Subsurface::new(
+                    arr1(&[0.6, 0.95, 0.7, 1.0]),
+                    arr1(&[0.25, 0.35, 0.25, 1.0]),
+                    1.6,
+                    Shading::COLOR,
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        let dims: [u32; 2] = [200, 100];
+        let camera = Camera::new(
+            90.0,
+            dims[0],
+            dims[1],
+            arr1(&[0.0, 0.0, 0.0, 1.0]),
+            arr1(&[0.0, 0.0, -1.0, 1.0]),
+            arr1(&[0.0, 1.0, 0.0, 0.0]),
+            0.0,
+        );
+
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 100, camera);
+        canvas.render_scene_rayon();
+        let image = canvas.grab_frame().as_flat_vec_u8();
+        let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
+        let _result = image_png.save(output_path.clone());
+
+        let test_image = canvas.grab_frame();
+        equals_to_baseline(test_image, output_path, 40.0);
+    }
+
+    #[test]
+    fn render_cutout() {
+        let mut output_path = init_image_testing();
+        output_path.push("render_cutout.png");
+
+        let mut materials = MaterialLibrary::new();
+        let mut actors = vec![];
+
+        actors.push(Box::new(Sphere {
+            center: arr1(&[0.0, -100.5, -1.0, 1.0]),
+            radius: 100.0,
+            material: materials.add(
+                "ground",
+                Box::new(Lambertian::new(
+                    arr1(&[0.8, 0.8, 0.0, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        // Checker of holes, the back faces show through them.
+        actors.push(Box::new(Sphere {
+            center: arr1(&[0.0, 0.0, -1.0, 1.0]),
+            radius: 0.5,
+            material: materials.add(
+                "cutout",
+                Box::new(Cutout::new(
+                    Box::new(Lambertian::new(
+                        arr1(&[0.1, 0.2, 0.5, 1.0]),
+                        Shading::COLOR,
+                    )),
+                    Box::new(CheckerTexture::new(
+                        arr1(&[1.0, 1.0, 1.0, 1.0]),
+                        arr1(&[1.0, 1.0, 1.0, 0.0]),
+                        6.0,
+                    )),
+                )),
+            ),
+        }) as Box<dyn RayTraceable>);
+
+        let dims: [u32; 2] = [200, 100];
+        let camera = Camera::new(
+            90.0,
+            dims[0],
+            dims[1],
+            arr1(&[0.0, 0.0, 0.0, 1.0]),
+            arr1(&[0.0, 0.0, -1.0, 1.0]),
+            arr1(&[0.0, 1.0, 0.0, 0.0]),
+            0.0,
+        );
+
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 100, camera);
+        canvas.render_scene_rayon();
+        let image = canvas.grab_frame().as_flat_vec_u8();
+        let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
+        let _result = image_png.save(output_path.clone());
+
+        let test_image = canvas.grab_frame();
+        equals_to_baseline(test_image, output_path, 28.0);
+    }
+
+    #[test]
+    fn render_path_depth() {
+        let scene = || {
+            let mut materials = MaterialLibrary::new();
+            let diffuse = materials.add(
+                "diffuse",
+                Box::new(Lambertian::new(
+                    arr1(&[0.5, 0.5, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            );
+            let actors = vec![
+                Box::new(Sphere::new(
+                    arr1(&[0.0, -100.5, -1.0, 1.0]),
+                    100.0,
+                    diffuse,
+                )) as Box<dyn RayTraceable>,
+                Box::new(Sphere::new(
+                    arr1(&[0.0, 0.0, -1.0, 1.0]),
+                    0.5,
+                    diffuse,
+                )) as Box<dyn RayTraceable>,
+            ];
+            let dims: [u32; 2] = [100, 50];
+            let camera = Camera::new(
+                90.0,
+                dims[0],
+                dims[1],
+                arr1(&[0.0, 0.0, 0.0, 1.0]),
+                arr1(&[0.0, 0.0, -1.0, 1.0]),
+                arr1(&[0.0, 1.0, 0.0, 0.0]),
+                0.0,
+            );
+
+            Canvas::new(dims[0], dims[1], actors, materials, 20, camera)
+        };
+        let mean = |image: &Image| {
+            let data = image.as_flat_vec_u8();
+            data.iter().map(|x| *x as f64).sum::<f64>() / data.len() as f64
+        };
+
+        // Camera rays only, nothing is lit but the background.
+        let mut canvas = scene();
+        canvas.integrator = Box::new(PathTracer {
+            max_depth: 1,
+            ..PathTracer::new()
+        });
+        canvas.render_scene_rayon();
+        let image = canvas.grab_frame();
+        assert!(image.get_value(50, 25, 0) == 0);
+        assert!(image.get_value(50, 0, 0) > 0);
+
+        // Russian roulette does not change the average brightness.
+        let mut canvas = scene();
+        canvas.integrator = Box::new(PathTracer {
+            roulette_depth: 50,
+            ..PathTracer::new()
+        });
+        canvas.render_scene_rayon();
+        let reference = mean(&canvas.grab_frame());
+
+        let mut canvas = scene();
+        canvas.integrator = Box::new(PathTracer {
+            roulette_depth: 1,
+            ..PathTracer::new()
+        });
+        canvas.render_scene_rayon();
+        let roulette = mean(&canvas.grab_frame());
+
+        assert!((roulette - reference).abs() < 0.02 * reference);
+    }
+
+    #[test]
+    fn render_ambient_occlusion() {
+        let mut output_path = init_image_testing();
+        output_path.push("render_ambient_occlusion.png");
+
+        let mut materials = MaterialLibrary::new();
+        let diffuse = materials.add(
+            "diffuse",
+            Box::new(Lambertian::new(
+                arr1(&[0.5, 0.5, 0.5, 1.0]),
+                Shading::COLOR,
+            )),
+        );
+        let actors = vec![
+            Box::new(Sphere::new(
+                arr1(&[0.0, -100.5, -1.0, 1.0]),
+                100.0,
+                diffuse,
+            )) as Box<dyn RayTraceable>,
+            Box::new(Sphere::new(arr1(&[-0.5, 0.0, -1.0, 1.0]), 0.5, diffuse))
+                as Box<dyn RayTraceable>,
+            Box::new(Sphere::new(arr1(&[0.5, 0.0, -1.0, 1.0]), 0.5, diffuse))
+                as Box<dyn RayTraceable>,
+        ];
+
+        let dims: [u32; 2] = [200, 100];
+        let camera = Camera::new(
+            90.0,
+            dims[0],
+            dims[1],
+            arr1(&[0.0, 0.0, 0.0, 1.0]),
+            arr1(&[0.0, 0.0, -1.0, 1.0]),
+            arr1(&[0.0, 1.0, 0.0, 0.0]),
+            0.0,
+        );
+
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 100, camera);
+        canvas.integrator = Box::new(AmbientOcclusion::new(0.5));
+        canvas.render_scene_rayon();
+        let image = canvas.grab_frame().as_flat_vec_u8();
+        let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
+        let _result = image_png.save(output_path.clone());
+
+        let test_image = canvas.grab_frame();
+        equals_to_baseline(test_image, output_path, 60.0);
+    }
+
+    #[test]
+    fn render_reproducible() {
+        // Glass (random reflection or refraction), diffuse bounces and a
+        // lens (random ray origins): the same seed gives the same image,
+        // serial or in parallel, for per-pixel and whole-image integrators,
+        // and for the ones with a preprocessing (the photons they shoot).
+        let canvas = |seed: u64, integrator: &str| {
+            let mut materials = MaterialLibrary::new();
+            let glass = materials.add(
+                "glass",
+                Box::new(Dielectric::new(
+                    arr1(&[1.0, 1.0, 1.0, 1.0]),
+                    Shading::COLOR,
+                    1.5,
+                )),
+            );
+            let diffuse = materials.add(
+                "diffuse",
+                Box::new(Lambertian::new(
+                    arr1(&[0.5, 0.5, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            );
+            let actors = vec![
+                Box::new(Sphere::new(
+                    arr1(&[0.0, -100.5, -1.0, 1.0]),
+                    100.0,
+                    diffuse,
+                )) as Box<dyn RayTraceable>,
+                Box::new(Sphere::new(arr1(&[0.0, 0.0, -1.0, 1.0]), 0.5, glass))
+                    as Box<dyn RayTraceable>,
+            ];
+            let camera = Camera::new(
+                90.0,
+                40,
+                20,
+                arr1(&[0.0, 0.0, 0.0, 1.0]),
+                arr1(&[0.0, 0.0, -1.0, 1.0]),
+                arr1(&[0.0, 1.0, 0.0, 0.0]),
+                0.1,
+            );
+
+            let mut canvas = Canvas::new(40, 20, actors, materials, 4, camera);
+            canvas.seed = seed;
+            if integrator == "metropolis" {
+                canvas.integrator = Box::new(Metropolis {
+                    bootstrap: 1000,
+                    ..Metropolis::default()
+                });
+            } else if integrator == "photons" {
+                let mut photon_mapper = PhotonMapper::new();
+                photon_mapper.photons = 2000;
+                canvas.integrator = Box::new(photon_mapper);
+            }
+            canvas
+        };
+
+        for integrator in ["path", "metropolis", "photons"].iter() {
+            let mut parallel = canvas(7, integrator);
+            parallel.render_scene_rayon();
+            let mut serial = canvas(7, integrator);
+            serial.render_scene();
+            let mut other = canvas(8, integrator);
+            other.render_scene_rayon();
+
+            let image = parallel.grab_frame().as_flat_vec_u8();
+            assert!(image == serial.grab_frame().as_flat_vec_u8());
+            assert!(image != other.grab_frame().as_flat_vec_u8());
+        }
+    }
+
+    #[test]
+    fn render_sampling_convergence() {
+        // Ambient occlusion (pixel position, lens and occlusion rays drawn
+        // from the sampler), compared with a many-sample reference: the
+        // low-discrepancy samplers are closer for the same sample count.
+        let render = |sampling: Sampling, samples: u32| {
+            let mut canvas = diffuse_sphere_scene(40, 20, samples, true);
+            canvas.integrator = Box::new(AmbientOcclusion::new(0.5));
+            canvas.sampling = sampling;
+            canvas.seed = samples as u64;
+            canvas.render_scene_rayon();
+            canvas.grab_frame().as_flat_vec_u8()
+        };
+
+        let reference = render(Sampling::Random, 1024);
+        let error = |sampling: Sampling| {
+            let image = render(sampling, 16);
+            image
+                .iter()
+                .zip(reference.iter())
+                .map(|(a, b)| (*a as f64 - *b as f64).abs())
+                .sum::<f64>()
+                / image.len() as f64
+        };
+
+        let random = error(Sampling::Random);
+        for sampling in [
+            Sampling::Stratified,
+            Sampling::Halton,
+            Sampling::Sobol,
+            Sampling::BlueNoise,
+        ]
+        .iter()
+        {
+            let low_discrepancy = error(*sampling);
+            assert!(
+                low_discrepancy < random,
+                "{:?}: {} vs {} (random)",
+                sampling,
+                low_discrepancy,
+                random
+            );
+        }
+    }
+
+    #[test]
+    fn render_adaptive_sampling() {
+        let mut output_path = init_image_testing();
+        output_path.push("render_adaptive_sampling.png");
+
+        // Only the noisy pixels (the diffuse sphere and ground, lit by the
+        // sky) keep being sampled, the sky converges with the minimum.
+        let dims: [u32; 2] = [100, 50];
+        let mut canvas = diffuse_sphere_scene(dims[0], dims[1], 8, true);
+        canvas.adaptive = Some(Adaptive {
+            max_samples: 256,
+            error: 0.02,
+        });
+        canvas.render_scene_rayon();
+        let image = canvas.grab_sample_counts().as_flat_vec_u8();
+        let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
+        let _result = image_png.save(output_path.clone());
+
+        let counts = canvas.sample_counts();
+        let sky = counts[5];
+        let sphere = counts[(dims[1] / 2 * dims[0] + dims[0] / 2) as usize];
+        assert!(sky == 8, "sky: {}", sky);
+        assert!(sphere > 64, "sphere: {}", sphere);
+        assert!(counts.iter().all(|count| *count % 8 == 0 && *count <= 256));
+    }
+
+    #[test]
+    fn render_progressive() {
+        // Every pass adds samples, the frame gets closer to the converged
+        // image. The first one is the same as a full render.
+        let canvas = |samples: u32| diffuse_sphere_scene(40, 20, samples, true);
+        let error = |a: &Image, b: &Image| {
+            a.as_flat_vec_u8()
+                .iter()
+                .zip(b.as_flat_vec_u8().iter())
+                .map(|(a, b)| (*a as f64 - *b as f64).abs())
+                .sum::<f64>()
+        };
+
+        let mut reference = canvas(1024);
+        reference.seed = 1;
+        reference.render_scene_rayon();
+        let reference = reference.grab_frame();
+
+        let mut full = canvas(4);
+        full.render_scene_rayon();
+        let mut progressive = canvas(4);
+        progressive.render_progressive(4);
+        let first = progressive.grab_frame();
+        assert!(first.as_flat_vec_u8() == full.grab_frame().as_flat_vec_u8());
+
+        for _pass in 1..16 {
+            progressive.render_progressive(4);
+        }
+        assert!(progressive.sample_counts().iter().all(|count| *count == 64));
+        let refined = progressive.grab_frame();
+        assert!(
+            error(&refined, &reference) < 0.5 * error(&first, &reference),
+            "{} vs {}",
+            error(&refined, &reference),
+            error(&first, &reference)
+        );
+
+        // Starting over.
+        progressive.reset_progressive();
+        assert!(progressive.sample_counts().iter().all(|count| *count == 0));
+        progressive.render_progressive(4);
+        assert!(
+            progressive.grab_frame().as_flat_vec_u8() == first.as_flat_vec_u8()
+        );
+    }
+
+    #[test]
+    fn render_tiles() {
+        // Every order covers each pixel once.
+        let orders =
+            [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Morton];
+        for order in orders.iter() {
+            let mut covered = vec![0; 50 * 30];
+            for tile in tiles(50, 30, 8, *order).iter() {
+                for y in tile.y..tile.y + tile.height {
+                    for x in tile.x..tile.x + tile.width {
+                        covered[(y * 50 + x) as usize] += 1;
+                    }
+                }
+            }
+            assert!(covered.iter().all(|count| *count == 1));
+            assert!(tiles(0, 30, 8, *order).is_empty());
+            assert!(tiles(50, 0, 8, *order).is_empty());
+        }
+
+        // Spiral starts at the center, Morton goes by 2x2 blocks.
+        let spiral = tiles(50, 30, 8, TileOrder::Spiral);
+        assert!(spiral[0].x <= 25 && 25 < spiral[0].x + 8);
+        assert!(spiral[0].y <= 15 && 15 < spiral[0].y + 8);
+        let morton: Vec<(u32, u32)> = tiles(50, 30, 8, TileOrder::Morton)
+            .iter()
+            .map(|tile| (tile.x, tile.y))
+            .collect();
+        assert!(morton[..5] == [(0, 0), (8, 0), (0, 8), (8, 8), (16, 0)]);
+
+        let canvas = || {
+            let mut canvas = diffuse_sphere_scene(50, 30, 4, false);
+            canvas.tile_size = 8;
+            canvas
+        };
+
+        // Same image whatever the order, each tile reported once.
+        let mut images = vec![];
+        for order in orders.iter() {
+            let done = Arc::new(AtomicUsize::new(0));
+            let mut canvas = canvas();
+            canvas.tile_order = *order;
+            let counter = done.clone();
+            canvas.on_tile = Some(Box::new(move |_tile: &Tile| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }));
+            canvas.render_scene_rayon();
+            assert!(done.load(Ordering::SeqCst) == 7 * 4);
+            assert!(!canvas.was_cancelled());
+            images.push(canvas.grab_frame().as_flat_vec_u8());
+        }
+        assert!(images.iter().all(|image| *image == images[0]));
+
+        // Cancelled after the first tile, the others are skipped.
+        let mut canvas = canvas();
+        let cancel = canvas.cancel_token();
+        canvas.on_tile = Some(Box::new(move |_tile: &Tile| cancel.cancel()));
+        canvas.render_scene();
+        assert!(canvas.was_cancelled());
+        let rendered =
+            canvas.sample_counts().iter().filter(|c| **c > 0).count();
+        assert!(rendered == 8 * 8);
+    }
+
+    #[test]
+    fn render_options() {
+        let scene = || {
+            let mut canvas = diffuse_sphere_scene(50, 30, 4, false);
+            canvas.tile_size = 8;
+            canvas
+        };
+
+        // Progress is reported per tile, up to every pixel and sample.
+        let reports = Arc::new(Mutex::new(vec![]));
+        let progress = reports.clone();
+        let options = RenderOptions {
+            on_progress: Some(Box::new(move |progress: &Progress| {
+                reports.lock().unwrap().push(*progress);
+            })),
+            ..RenderOptions::default()
+        };
+        let mut canvas = scene();
+        canvas.render_scene_with(&options);
+        let progress = progress.lock().unwrap();
+        assert!(progress.len() == 7 * 4);
+        let last = progress
+            .iter()
+            .max_by(|a, b| a.samples.cmp(&b.samples))
+            .unwrap();
+        assert!((last.percent - 100.0).abs() < 1e-9);
+        assert!(last.samples == 50 * 30 * 4);
+        assert!(last.eta == Duration::from_secs(0));
+        assert!(!canvas.was_cancelled());
+
+        // Cancelled by the token after the first tile.
+        let cancel = CancelToken::default();
+        let token = cancel.clone();
+        let options = RenderOptions {
+            on_progress: Some(Box::new(move |_progress: &Progress| {
+                token.cancel()
+            })),
+            cancel: Some(cancel),
+            ..RenderOptions::default()
+        };
+        let mut canvas = scene();
+        canvas.render_scene_with(&options);
+        assert!(canvas.was_cancelled());
+        let rendered =
+            canvas.sample_counts().iter().filter(|c| **c > 0).count();
+        assert!((8 * 8..50 * 30).contains(&rendered));
+
+        // Out of time, the frame is the best estimate so far.
+        let options = RenderOptions {
+            time_budget: Some(Duration::from_secs(0)),
+            ..RenderOptions::default()
+        };
+        let mut canvas = scene();
+        canvas.render_progressive(4);
+        let frame = canvas.grab_frame().as_flat_vec_u8();
+        canvas.render_progressive_with(4, &options).unwrap();
+        assert!(canvas.was_cancelled());
+        assert!(canvas.sample_counts().iter().all(|count| *count == 4));
+        assert!(canvas.grab_frame().as_flat_vec_u8() == frame);
+        canvas.render_scene_with(&options);
+        assert!(canvas.sample_counts().iter().all(|count| *count == 0));
+
+        // A whole-image integrator with nothing in the crop reports no
+        // progress, and no time left.
+        let reports = Arc::new(Mutex::new(vec![]));
+        let progress = reports.clone();
+        let options = RenderOptions {
+            on_progress: Some(Box::new(move |progress: &Progress| {
+                reports.lock().unwrap().push(*progress);
+            })),
+            crop: Some(Tile {
+                x: 60,
+                y: 40,
+                width: 10,
+                height: 10,
+            }),
+            ..RenderOptions::default()
+        };
+        let mut canvas = scene();
+        canvas.integrator = Box::new(Metropolis {
+            bootstrap: 1000,
+            ..Metropolis::default()
+        });
+        canvas.render_scene_with(&options);
+        let progress = progress.lock().unwrap();
+        assert!(!progress.is_empty());
+        assert!(progress
+            .iter()
+            .all(|p| p.samples == 0 && p.eta == Duration::ZERO));
+    }
+
+    #[test]
+    fn render_checkpoint() {
+        let scene = |width: u32, height: u32| {
+            diffuse_sphere_scene(width, height, 2, false)
+        };
+        let mut path = init_image_testing();
+        path.push("render_checkpoint.bin");
+
+        let mut uninterrupted = scene(50, 30);
+        uninterrupted.seed = 7;
+        for _pass in 0..3 {
+            uninterrupted.render_progressive(2);
+        }
+
+        // Saved after every pass, resumed by another Canvas.
+        let options = RenderOptions {
+            checkpoint: Some(Checkpoint {
+                path: path.clone(),
+                interval: Duration::from_secs(0),
+            }),
+            ..RenderOptions::default()
+        };
+        let mut interrupted = scene(50, 30);
+        interrupted.seed = 7;
+        for _pass in 0..2 {
+            interrupted.render_progressive_with(2, &options).unwrap();
+        }
+        let mut resumed = scene(50, 30);
+        resumed.load_checkpoint(&path).unwrap();
+        assert!(
+            resumed.grab_frame().as_flat_vec_u8()
+                == interrupted.grab_frame().as_flat_vec_u8()
+        );
+        resumed.render_progressive(2);
+        assert!(resumed.seed == 7);
+        assert!(resumed.sample_counts() == uninterrupted.sample_counts());
+        assert!(
+            resumed.grab_frame().as_flat_vec_u8()
+                == uninterrupted.grab_frame().as_flat_vec_u8()
+        );
+
+        // Not of the same size or settings (or not a checkpoint at all).
+        assert!(scene(30, 50).load_checkpoint(&path).is_err());
+        let mut other = scene(50, 30);
+        other.samples = 4;
+        assert!(other.load_checkpoint(&path).is_err());
+        let mut other = scene(50, 30);
+        other.sampling = Sampling::Sobol;
+        assert!(other.load_checkpoint(&path).is_err());
+        let mut other = scene(50, 30);
+        other.filter = Filter::new(FilterKind::Tent);
+        assert!(other.load_checkpoint(&path).is_err());
+        let mut other = scene(50, 30);
+        other.spectral = true;
+        assert!(other.load_checkpoint(&path).is_err());
+        path.set_extension("missing");
+        assert!(scene(50, 30).load_checkpoint(&path).is_err());
+
+        // Saved next to the file first (then renamed), also when it is a
+        // .tmp one.
+        path.set_extension("tmp");
+        resumed.save_checkpoint(&path).unwrap();
+        assert!(scene(50, 30).load_checkpoint(&path).is_ok());
+        path.set_extension("tmp.tmp");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn render_crop() {
+        let scene = || diffuse_sphere_scene(50, 30, 4, false);
+        let crop = Tile {
+            x: 12,
+            y: 5,
+            width: 20,
+            height: 10,
+        };
+        let options = RenderOptions {
+            crop: Some(crop),
+            ..RenderOptions::default()
+        };
+
+        // Only the crop is rendered, as in the whole frame.
+        let mut full = scene();
+        full.render_scene_rayon();
+        let mut cropped = scene();
+        cropped.render_scene_with(&options);
+        let image = cropped.grab_crop(&crop);
+        assert!(image.width == 20 && image.height == 10);
+        assert!(
+            image.as_flat_vec_u8()
+                == full.grab_frame().crop(12, 5, 20, 10).as_flat_vec_u8()
+        );
+
+        // The other pixels are untouched.
+        let frame = full.grab_frame();
+        full.samples = 2;
+        full.render_scene_with(&options);
+        for (index, count) in full.sample_counts().iter().enumerate() {
+            let (x, y) = Image::pixel_coordinate(50, index);
+            if crop.contains(x, y) {
+                assert!(*count == 2);
+            } else {
+                assert!(*count == 4);
+                assert!(
+                    full.grab_frame().data[index].data
+                        == frame.data[index].data
+                );
+            }
+        }
+
+        // Crops are clipped to the frame.
+        let outside = Tile {
+            x: 40,
+            y: 20,
+            width: 20,
+            height: 20,
+        };
+        let image = full.grab_crop(&outside);
+        assert!(image.width == 10 && image.height == 10);
+
+        // Out of the frame nothing is rendered, and past u32::MAX crops
+        // are still clipped. Neither is valid.
+        let outside = Tile {
+            x: 60,
+            y: 0,
+            width: 10,
+            height: 10,
+        };
+        let mut canvas = scene();
+        canvas.render_scene_with(&RenderOptions {
+            crop: Some(outside),
+            ..RenderOptions::default()
+        });
+        assert!(canvas.sample_counts().iter().all(|count| *count == 0));
+        assert!(canvas.grab_crop(&outside).size() == 0);
+        assert!(canvas.check_crop(&outside).is_err());
+        let overflowing = Tile {
+            x: 10,
+            y: 10,
+            width: u32::MAX,
+            height: u32::MAX,
+        };
+        canvas.render_scene_with(&RenderOptions {
+            crop: Some(overflowing),
+            ..RenderOptions::default()
+        });
+        let image = canvas.grab_crop(&overflowing);
+        assert!(image.width == 40 && image.height == 20);
+        assert!(canvas.check_crop(&overflowing).is_err());
+
+        // Only the non-empty crops within the frame are valid.
+        assert!(canvas.check_crop(&crop).is_ok());
+        assert!(canvas.check_crop(&Tile { width: 0, ..crop }).is_err());
+        assert!(canvas
+            .check_crop(&Tile {
+                x: 0,
+                y: 0,
+                width: 50,
+                height: 30,
+            })
+            .is_ok());
+    }
+
     #[test]
     fn render_book_cover() {
         let mut output_path = init_image_testing();
         output_path.push("render_book_cover.png");
 
-        let actors = scenes::random_book_cover();
+        let mut materials = MaterialLibrary::new();
+        let actors = scenes::random_book_cover(&mut materials);
 
         let dims: [u32; 2] = [200, 133];
         //let dims: [u32; 2] = [1200, 800];
@@ -645,7 +1955,8 @@ mod tests {
             0.2,
         );
 
-        let mut canvas = Canvas::new(dims[0], dims[1], actors, 10, camera);
+        let mut canvas =
+            Canvas::new(dims[0], dims[1], actors, materials, 10, camera);
         canvas.render_scene_rayon();
         let image = canvas.grab_frame().as_flat_vec_u8();
         let image_png = RgbaImage::from_raw(dims[0], dims[1], image).unwrap();
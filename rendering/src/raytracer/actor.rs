@@ -1,16 +1,29 @@
 use crate::raytracer::common::Ray;
+use crate::raytracer::common::Vec4;
 use crate::raytracer::common::AABB;
-use crate::raytracer::material::Lambertian;
-use crate::raytracer::material::Scattering;
-use crate::raytracer::material::Shading;
+use crate::raytracer::material::MaterialId;
+use crate::raytracer::material::MaterialLibrary;
+use crate::raytracer::sampler::{self, RandomSampler};
 use ndarray::{arr1, Array1};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 ///use std::cmp::min;
 
+/**
+ * Besides the normal, the tangent (along u) and bitangent (along v) complete
+ * the surface frame at the hit point, in which tangent-space normal maps
+ * are defined. (u, v) are the surface (texture) coordinates.
+ *
+ * The material is a handle into the MaterialLibrary of the scene.
+ */
 pub struct Hit {
     pub t: f64,
     pub point: Array1<f64>,
     pub normal: Array1<f64>,
-    pub material: Box<dyn Scattering>,
+    pub tangent: Array1<f64>,
+    pub bitangent: Array1<f64>,
+    pub uv: Array1<f64>,
+    pub material: MaterialId,
 }
 
 impl Hit {
@@ -19,10 +32,10 @@ impl Hit {
             t: 0.0,
             point: arr1(&[0.0, 0.0, 0.0, 1.0]),
             normal: arr1(&[1.0, 1.0, 1.0, 0.0]),
-            material: Box::new(Lambertian::new(
-                arr1(&[0.0, 0.0, 1.0, 1.0]),
-                Shading::COLOR,
-            )),
+            tangent: arr1(&[1.0, 0.0, 0.0, 0.0]),
+            bitangent: arr1(&[0.0, 1.0, 0.0, 0.0]),
+            uv: arr1(&[0.0, 0.0]),
+            material: MaterialId(0),
         }
     }
 
@@ -31,7 +44,10 @@ impl Hit {
             t: hit.t,
             point: hit.point.clone(),
             normal: hit.normal.clone(),
-            material: hit.material.clone(),
+            tangent: hit.tangent.clone(),
+            bitangent: hit.bitangent.clone(),
+            uv: hit.uv.clone(),
+            material: hit.material,
         }
     }
 }
@@ -61,22 +77,34 @@ pub trait Hittable {
      * for infinite planes, for instance).
      */
     fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB>;
+
+    /**
+     * Uniformly distributed point on the surface (e.g. to sample lights),
+     * for the actors that support it.
+     */
+    fn sample_surface(&self) -> Option<Hit> {
+        None
+    }
+
+    fn area(&self) -> f64 {
+        0.0
+    }
 }
 
-pub trait RayTraceable: Hittable + Sync {}
+pub trait RayTraceable: Hittable + Sync + Send {}
 
 // -----------------------------------------------------------------------------
 pub struct Sphere {
     pub center: Array1<f64>,
     pub radius: f64,
-    pub material: Box<dyn Scattering>,
+    pub material: MaterialId,
 }
 
 impl Sphere {
     pub fn new(
         center: Array1<f64>,
         radius: f64,
-        material: Box<dyn Scattering>,
+        material: MaterialId,
     ) -> Sphere {
         Sphere {
             center,
@@ -95,6 +123,59 @@ impl Sphere {
         let n = (point_sphere.clone() - self.center.clone()) / self.radius;
         n
     }
+
+    /**
+     * Spherical coordinates of the (outward) unit normal, mapped to [0, 1]:
+     * u = phi / 2 PI, the angle around the y axis (starting at -x)
+     * v = theta / PI, the angle from the bottom (-y) to the top (+y)
+     */
+    fn compute_uv(&self, normal: &Array1<f64>) -> Array1<f64> {
+        let theta = (-normal[1]).clamp(-1.0, 1.0).acos();
+        let phi = (-normal[2]).atan2(normal[0]) + std::f64::consts::PI;
+
+        arr1(&[
+            phi / (2.0 * std::f64::consts::PI),
+            theta / std::f64::consts::PI,
+        ])
+    }
+
+    /**
+     * Tangent along increasing u (dP/du, around the y axis) and bitangent
+     * completing the (right-handed) frame, pointing towards increasing v.
+     * At the poles, where dP/du vanishes, any tangent is valid.
+     */
+    fn compute_tangents(
+        &self,
+        normal: &Array1<f64>,
+    ) -> (Array1<f64>, Array1<f64>) {
+        let mut tangent = arr1(&[normal[2], 0.0, -normal[0], 0.0]);
+        if Vec4::squared_length(tangent.view()) < 1e-12 {
+            tangent = arr1(&[1.0, 0.0, 0.0, 0.0]);
+        }
+        let tangent = Vec4::normalize(tangent);
+        let bitangent = Vec4::cross(normal.clone(), tangent.clone());
+
+        (tangent, bitangent)
+    }
+
+    fn fill_record(&self, ray: &Ray, t: f64, record: &mut Hit) {
+        record.t = t;
+        self.fill_surface(ray.point_at_parameter(t), record);
+    }
+
+    fn fill_surface(&self, point: Array1<f64>, record: &mut Hit) {
+        record.point = point;
+        record.normal = self.compute_normal(&record.point);
+
+        // Surface coordinates do not depend on the normal orientation
+        // (spheres with a negative radius have inward normals).
+        let outward = &record.normal * self.radius.signum();
+        record.uv = self.compute_uv(&outward);
+        let (tangent, bitangent) = self.compute_tangents(&record.normal);
+        record.tangent = tangent;
+        record.bitangent = bitangent;
+        record.material = self.material;
+    }
 }
 
 impl Hittable for Sphere {
@@ -129,20 +210,14 @@ impl Hittable for Sphere {
             // Solution (-) In range ?
             let t = (-b - discriminant.sqrt()) / (a);
             if t_min < t && t < t_max {
-                record.t = t;
-                record.point = ray.point_at_parameter(t);
-                record.normal = self.compute_normal(&ray.point_at_parameter(t));
-                record.material = self.material.clone();
+                self.fill_record(ray, t, record);
                 return true;
             }
 
             // Solution (+) In range ?
             let t = (-b + discriminant.sqrt()) / (a);
             if t_min < t && t < t_max {
-                record.t = t;
-                record.point = ray.point_at_parameter(t);
-                record.normal = self.compute_normal(&ray.point_at_parameter(t));
-                record.material = self.material.clone();
+                self.fill_record(ray, t, record);
                 return true;
             }
         }
@@ -161,18 +236,156 @@ impl Hittable for Sphere {
             self.center.clone() + radius,
         ))
     }
+
+    /**
+     * Uniform on the sphere: z = 1 - 2 u is uniform in [-1, 1]
+     * (Archimedes' hat-box theorem) and phi = 2 PI v.
+     */
+    fn sample_surface(&self) -> Option<Hit> {
+        let z: f64 = 1.0 - 2.0 * sampler::next_1d();
+        let phi = 2.0 * std::f64::consts::PI * sampler::next_1d();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let direction = arr1(&[r * phi.cos(), r * phi.sin(), z, 0.0]);
+
+        let mut record = Hit::new();
+        self.fill_surface(
+            &self.center + &(self.radius.abs() * direction),
+            &mut record,
+        );
+        Some(record)
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * std::f64::consts::PI * self.radius * self.radius
+    }
 }
 
 impl RayTraceable for Sphere {}
 
 // -----------------------------------------------------------------------------
+/**
+ * The actors of the scene, together with the library their materials are
+ * registered in.
+ *
+ * Lights are the actors made of emitting materials (that can be sampled),
+ * found when the list is created.
+ */
 pub struct HittableList {
     pub actors: Vec<Box<dyn RayTraceable>>,
+    pub materials: MaterialLibrary,
+    pub lights: Vec<usize>,
 }
 
 impl HittableList {
-    pub fn new(actors: Vec<Box<dyn RayTraceable>>) -> HittableList {
-        HittableList { actors }
+    pub fn new(
+        actors: Vec<Box<dyn RayTraceable>>,
+        materials: MaterialLibrary,
+    ) -> HittableList {
+        // Probing the surface draws a point, from a sampler of its own so
+        // that it does not depend on (nor needs) the one of the thread.
+        let probe = RandomSampler::seeded(0, 0);
+        let lights = sampler::with_sampler(Box::new(probe), || {
+            actors
+                .iter()
+                .enumerate()
+                .filter(|(_, actor)| match actor.sample_surface() {
+                    Some(hit) => {
+                        materials.get(hit.material).emission(&hit).is_some()
+                    }
+                    None => false,
+                })
+                .map(|(index, _)| index)
+                .collect()
+        });
+
+        HittableList {
+            actors,
+            materials,
+            lights,
+        }
+    }
+}
+
+impl HittableList {
+    /**
+     * Whether anything (opaque) is hit within (t_min, t_max), e.g. for
+     * shadow rays. Stops at the first hit instead of looking for the
+     * closest one.
+     */
+    pub fn is_occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let mut record = Hit::new();
+
+        self.actors.iter().any(|actor| {
+            is_hit_opaque(
+                &**actor,
+                &self.materials,
+                ray,
+                t_min,
+                t_max,
+                &mut record,
+            )
+        })
+    }
+
+    /**
+     * Point on the lights, picked uniformly over their total area, and its
+     * (area) pdf. None without lights.
+     */
+    pub fn sample_light(&self) -> Option<(Hit, f64)> {
+        let area = self.light_area();
+        if area <= 0.0 {
+            return None;
+        }
+
+        let mut remaining = sampler::gen_range(0.0, area);
+        for index in self.lights.iter() {
+            let actor = &self.actors[*index];
+            remaining -= actor.area();
+            if remaining < 0.0 {
+                return actor.sample_surface().map(|hit| (hit, 1.0 / area));
+            }
+        }
+
+        // Rounding, the last light.
+        self.actors[*self.lights.last().unwrap()]
+            .sample_surface()
+            .map(|hit| (hit, 1.0 / area))
+    }
+
+    /**
+     * Total area of the lights, the pdf of sample_light is its inverse.
+     */
+    pub fn light_area(&self) -> f64 {
+        self.lights
+            .iter()
+            .map(|index| self.actors[*index].area())
+            .sum()
+    }
+
+    /**
+     * Number of actor intersection tests needed to find the closest hit
+     * (including the ones repeated past hits rejected by the alpha test).
+     */
+    pub fn traversal_cost(&self, ray: &Ray, t_min: f64, t_max: f64) -> usize {
+        let mut tests = 0;
+        let mut closest_so_far = t_max;
+        let mut record = Hit::new();
+
+        for actor in self.actors.iter() {
+            if is_hit_opaque_counted(
+                &**actor,
+                &self.materials,
+                ray,
+                t_min,
+                closest_so_far,
+                &mut record,
+                &mut tests,
+            ) {
+                closest_so_far = record.t;
+            }
+        }
+
+        tests
     }
 }
 
@@ -195,7 +408,14 @@ impl Hittable for HittableList {
         let mut temp_record = Hit::new();
 
         for actor in self.actors.iter() {
-            if actor.is_hit(&ray, t_min, closest_so_far, &mut temp_record) {
+            if is_hit_opaque(
+                &**actor,
+                &self.materials,
+                &ray,
+                t_min,
+                closest_so_far,
+                &mut temp_record,
+            ) {
                 hit_anything = true;
                 closest_so_far = temp_record.t;
 
@@ -220,7 +440,7 @@ impl Hittable for HittableList {
             }
         };
 
-        let index: usize = 1;
+        let mut index: usize = 1;
         while index < self.actors.len() {
             let next_aabb = match self.actors[index].bounding_box(t0, t1) {
                 Some(aabb) => aabb,
@@ -230,6 +450,7 @@ impl Hittable for HittableList {
             };
 
             result = surrounding_box(next_aabb, result);
+            index += 1;
         }
 
         Some(result)
@@ -261,6 +482,76 @@ impl Hittable for BVHNode {
     }
 }
 
+/**
+ * Stochastic alpha test, the hit is kept with probability of the opacity
+ * of its material. The random number is a hash of the ray and the hit
+ * distance, so that the same ray always gets the same answer (e.g. when it
+ * is traced again to find a boundary).
+ */
+fn alpha_test(ray: &Ray, hit: &Hit, materials: &MaterialLibrary) -> bool {
+    let opacity = materials.get(hit.material).opacity(hit);
+    if opacity >= 1.0 {
+        return true;
+    } else if opacity <= 0.0 {
+        return false;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    for value in ray.origin.iter().chain(ray.direction.iter()) {
+        value.to_bits().hash(&mut hasher);
+    }
+    hit.t.to_bits().hash(&mut hasher);
+    let random = (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+
+    random < opacity
+}
+
+/**
+ * Closest hit that passes the alpha test. Hits that fail it are skipped by
+ * querying the actor again past them, which is what lists and acceleration
+ * structures use to hit their actors.
+ */
+pub fn is_hit_opaque(
+    actor: &dyn Hittable,
+    materials: &MaterialLibrary,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+    record: &mut Hit,
+) -> bool {
+    let mut tests = 0;
+
+    is_hit_opaque_counted(
+        actor, materials, ray, t_min, t_max, record, &mut tests,
+    )
+}
+
+/**
+ * Same as is_hit_opaque, also counting the intersection tests done.
+ */
+fn is_hit_opaque_counted(
+    actor: &dyn Hittable,
+    materials: &MaterialLibrary,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+    record: &mut Hit,
+    tests: &mut usize,
+) -> bool {
+    let mut t_min = t_min;
+
+    *tests += 1;
+    while actor.is_hit(ray, t_min, t_max, record) {
+        if alpha_test(ray, record, materials) {
+            return true;
+        }
+        t_min = record.t;
+        *tests += 1;
+    }
+
+    false
+}
+
 /**
  * Computes an AABB surrounding the two input AABBs.
  */
@@ -288,12 +579,17 @@ fn surrounding_box(box_a: AABB, box_b: AABB) -> AABB {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::raytracer::material::Cutout;
+    use crate::raytracer::material::Lambertian;
     use crate::raytracer::material::Primary;
+    use crate::raytracer::material::Shading;
+    use crate::raytracer::texture::CheckerTexture;
+    use crate::raytracer::texture::ConstantTexture;
+    use crate::raytracer::texture::Texture;
 
     #[test]
     fn aabb_sphere_origin() {
-        let material =
-            Box::new(Primary::new(arr1(&[1.0, 0.0, 0.0, 1.0]), Shading::COLOR));
+        let material = MaterialId(0);
         let sphere = Sphere::new(arr1(&[0.0, 0.0, 0.0, 1.0]), 1.0, material);
         let result_aabb = sphere.bounding_box(0.0, 0.0);
 
@@ -314,8 +610,7 @@ mod tests {
 
     #[test]
     fn aabb_sphere_shifted() {
-        let material =
-            Box::new(Primary::new(arr1(&[1.0, 0.0, 0.0, 1.0]), Shading::COLOR));
+        let material = MaterialId(0);
         let sphere = Sphere::new(arr1(&[1.0, 1.0, 1.0, 1.0]), 0.5, material);
         let result_aabb = sphere.bounding_box(0.0, 0.0);
 
@@ -336,12 +631,10 @@ mod tests {
 
     #[test]
     fn aabb_surrounding() {
-        let material =
-            Box::new(Primary::new(arr1(&[1.0, 0.0, 0.0, 1.0]), Shading::COLOR));
+        let material = MaterialId(0);
         let sphere_1 = Sphere::new(arr1(&[1.0, 1.0, 1.0, 1.0]), 0.5, material);
 
-        let material =
-            Box::new(Primary::new(arr1(&[1.0, 0.0, 0.0, 1.0]), Shading::COLOR));
+        let material = MaterialId(0);
         let sphere_2 = Sphere::new(arr1(&[0.0, 0.0, 0.0, 1.0]), 0.5, material);
 
         let aabb_surr = surrounding_box(
@@ -355,4 +648,219 @@ mod tests {
         assert!(min == arr1(&[-0.5, -0.5, -0.5, 1.0]));
         assert!(max == arr1(&[1.5, 1.5, 1.5, 1.0]));
     }
+
+    #[test]
+    fn sphere_uv_tangents() {
+        let material = MaterialId(0);
+        let sphere = Sphere::new(arr1(&[0.0, 0.0, -2.0, 1.0]), 0.5, material);
+
+        // Front-most point, seen from the origin.
+        let ray =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, -1.0, 0.0]));
+        let mut record = Hit::new();
+        assert!(sphere.is_hit(&ray, 0.0, 10.0, &mut record));
+
+        assert!((record.uv[0] - 0.25).abs() < 1e-9);
+        assert!((record.uv[1] - 0.5).abs() < 1e-9);
+
+        // Orthonormal frame, with the tangent along +x and bitangent along
+        // +y (increasing u and v respectively).
+        assert!((record.tangent.clone() - arr1(&[1.0, 0.0, 0.0, 0.0]))
+            .iter()
+            .all(|x| x.abs() < 1e-9));
+        assert!((record.bitangent.clone() - arr1(&[0.0, 1.0, 0.0, 0.0]))
+            .iter()
+            .all(|x| x.abs() < 1e-9));
+        assert!(record.tangent.dot(&record.normal).abs() < 1e-9);
+    }
+
+    #[test]
+    fn alpha_cutout() {
+        let mut materials = MaterialLibrary::new();
+        let mut cutout = |name: &str, opacity: Box<dyn Texture>| {
+            materials.add(
+                name,
+                Box::new(Cutout::new(
+                    Box::new(Primary::new(
+                        arr1(&[1.0, 0.0, 0.0, 1.0]),
+                        Shading::COLOR,
+                    )),
+                    opacity,
+                )),
+            )
+        };
+        let transparent = cutout(
+            "transparent",
+            Box::new(ConstantTexture::new(arr1(&[1.0, 1.0, 1.0, 0.0]))),
+        );
+        let opaque = cutout(
+            "opaque",
+            Box::new(ConstantTexture::new(arr1(&[1.0, 1.0, 1.0, 1.0]))),
+        );
+        let translucent = cutout(
+            "translucent",
+            Box::new(ConstantTexture::new(arr1(&[1.0, 1.0, 1.0, 0.25]))),
+        );
+        let checker = cutout(
+            "checker",
+            Box::new(CheckerTexture::new(
+                arr1(&[1.0, 1.0, 1.0, 0.0]),
+                arr1(&[1.0, 1.0, 1.0, 1.0]),
+                2.0,
+            )),
+        );
+
+        // Fully transparent sphere in front of an opaque one.
+        let ray =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, -1.0, 0.0]));
+        let world = HittableList::new(
+            vec![
+                Box::new(Sphere::new(
+                    arr1(&[0.0, 0.0, -2.0, 1.0]),
+                    0.5,
+                    transparent,
+                )) as Box<dyn RayTraceable>,
+                Box::new(Sphere::new(arr1(&[0.0, 0.0, -4.0, 1.0]), 0.5, opaque))
+                    as Box<dyn RayTraceable>,
+            ],
+            materials.clone(),
+        );
+        let mut record = Hit::new();
+        assert!(world.is_hit(&ray, 0.0, 10.0, &mut record));
+        assert!((record.t - 3.5).abs() < 1e-9);
+        assert!(record.material == opaque);
+        assert!(world.is_occluded(&ray, 0.0, 10.0));
+        assert!(!world.is_occluded(&ray, 0.0, 3.0));
+
+        // Front half of the checker is see-through, the ray then hits the
+        // back face of the same sphere (u ~ 0.75, v < 0.5).
+        let ray = Ray::new(
+            arr1(&[0.0, -0.1, 0.0, 1.0]),
+            arr1(&[0.0, 0.0, -1.0, 0.0]),
+        );
+        let sphere = Sphere::new(arr1(&[0.0, 0.0, -2.0, 1.0]), 0.5, checker);
+        assert!(is_hit_opaque(
+            &sphere,
+            &materials,
+            &ray,
+            0.0,
+            10.0,
+            &mut record
+        ));
+        assert!((record.t - (2.0 + 0.24_f64.sqrt())).abs() < 1e-9);
+
+        // Partial opacity lets through about that fraction of the rays.
+        let world = HittableList::new(
+            vec![Box::new(Sphere::new(
+                arr1(&[0.0, 0.0, -200.0, 1.0]),
+                100.0,
+                translucent,
+            )) as Box<dyn RayTraceable>],
+            materials,
+        );
+        let samples = 10000;
+        let hits = (0..samples)
+            .filter(|index| {
+                let x = (*index as f64 / samples as f64) - 0.5;
+                let ray = Ray::new(
+                    arr1(&[x, 0.0, 0.0, 1.0]),
+                    arr1(&[0.0, 0.0, -1.0, 0.0]),
+                );
+                world.is_hit(&ray, 0.0, 1000.0, &mut Hit::new())
+            })
+            .count();
+        // Either face of the sphere (0.25 + 0.75 * 0.25).
+        let expected = 0.4375 * samples as f64;
+        assert!((hits as f64 - expected).abs() < 0.05 * samples as f64);
+    }
+
+    #[test]
+    fn material_library() {
+        let mut materials = MaterialLibrary::new();
+        let red = materials.add(
+            "red",
+            Box::new(Primary::new(arr1(&[1.0, 0.0, 0.0, 1.0]), Shading::COLOR)),
+        );
+        let green = materials.add(
+            "green",
+            Box::new(Primary::new(arr1(&[0.0, 1.0, 0.0, 1.0]), Shading::COLOR)),
+        );
+        assert!(red != green);
+        assert!(materials.id("green") == Some(green));
+        assert!(materials.id("blue").is_none());
+        assert!(materials.len() == 2);
+
+        // Actors share the material, hits only carry its handle.
+        let world = HittableList::new(
+            vec![
+                Box::new(Sphere::new(arr1(&[0.0, 0.0, -2.0, 1.0]), 0.5, red))
+                    as Box<dyn RayTraceable>,
+                Box::new(Sphere::new(arr1(&[0.0, 0.0, -4.0, 1.0]), 0.5, red))
+                    as Box<dyn RayTraceable>,
+            ],
+            materials,
+        );
+        let ray =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, -1.0, 0.0]));
+        let mut record = Hit::new();
+        assert!(world.is_hit(&ray, 0.0, 10.0, &mut record));
+        assert!(record.material == red);
+        let color = world.materials.get(record.material).color(&record);
+        assert!(color == arr1(&[1.0, 0.0, 0.0, 1.0]));
+
+        // Registering under the same name edits the material in place.
+        let mut world = world;
+        let edited = world.materials.add(
+            "red",
+            Box::new(Primary::new(arr1(&[0.5, 0.0, 0.0, 1.0]), Shading::COLOR)),
+        );
+        assert!(edited == red);
+        assert!(world.materials.len() == 2);
+        let color = world.materials.get(record.material).color(&record);
+        assert!(color == arr1(&[0.5, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn lights_sampling() {
+        let mut materials = MaterialLibrary::new();
+        let light = materials.add(
+            "light",
+            Box::new(Primary::new(arr1(&[4.0, 4.0, 4.0, 1.0]), Shading::COLOR)),
+        );
+        let diffuse = materials.add(
+            "diffuse",
+            Box::new(Lambertian::new(
+                arr1(&[0.5, 0.5, 0.5, 1.0]),
+                Shading::COLOR,
+            )),
+        );
+        let center = arr1(&[1.0, 2.0, -3.0, 1.0]);
+        let world = HittableList::new(
+            vec![
+                Box::new(Sphere::new(arr1(&[0.0, 0.0, 0.0, 1.0]), 1.0, diffuse))
+                    as Box<dyn RayTraceable>,
+                Box::new(Sphere::new(center.clone(), 0.5, light))
+                    as Box<dyn RayTraceable>,
+            ],
+            materials,
+        );
+
+        // Only the emitting actor is a light.
+        assert!(world.lights == vec![1]);
+        assert!((world.light_area() - std::f64::consts::PI).abs() < 1e-9);
+
+        let seeded = RandomSampler::seeded(0, 0);
+        sampler::with_sampler(Box::new(seeded), || {
+            for _ in 0..100 {
+                let (hit, pdf) = world.sample_light().unwrap();
+                let radial = &hit.point - &center;
+                assert!((Vec4::l2_norm(radial.view()) - 0.5).abs() < 1e-9);
+                assert!(
+                    Vec4::l2_norm((&radial * 2.0 - &hit.normal).view()) < 1e-9
+                );
+                assert!(hit.material == light);
+                assert!((pdf - 1.0 / std::f64::consts::PI).abs() < 1e-9);
+            }
+        });
+    }
 }
@@ -1,7 +1,7 @@
 use crate::raytracer::common::Ray;
 use crate::raytracer::common::Vec4;
+use crate::raytracer::sampler;
 use ndarray::{arr1, arr2, Array1, Array2};
-use rand::Rng;
 
 /**
  * Transformation is from pixel-coordinates to world-coordinates.
@@ -81,18 +81,29 @@ fn compute_camera_orientation(
     to_world
 }
 
+/**
+ * Uniformly distributed point in the unit disk, by the concentric mapping
+ * of the square to the disk (which keeps strata of the square together).
+ *
+ * Reference:
+ * * Shirley and Chiu, "A Low Distortion Map Between Disk and Square",
+ *   1997.
+ */
 pub fn random_in_unit_disk() -> Array1<f64> {
-    let mut p = arr1(&[std::f64::MAX, std::f64::MAX]);
-    let mut rng = rand::thread_rng();
-    let min = -1.0;
-    let max = 1.0;
-
-    while p.dot(&p) >= 1.0 {
-        p[0] = rng.gen_range(min, max);
-        p[1] = rng.gen_range(min, max);
+    let u = sampler::gen_range(-1.0, 1.0);
+    let v = sampler::gen_range(-1.0, 1.0);
+    if u == 0.0 && v == 0.0 {
+        return arr1(&[0.0, 0.0, 0.0, 1.0]);
     }
 
-    arr1(&[p[0], p[1], 0.0, 1.0])
+    let quarter_pi = std::f64::consts::FRAC_PI_4;
+    let (r, theta) = if u.abs() > v.abs() {
+        (u, quarter_pi * (v / u))
+    } else {
+        (v, 2.0 * quarter_pi - quarter_pi * (u / v))
+    };
+
+    arr1(&[r * theta.cos(), r * theta.sin(), 0.0, 1.0])
 }
 
 impl Camera {
@@ -156,6 +167,7 @@ impl Camera {
             direction: Vec4::normalize(
                 point_world - self.origin.clone() - rd.clone(),
             ),
+            wavelength: None,
         }
     }
 
@@ -72,9 +72,14 @@ impl Vec4 {
     }
 }
 
+/**
+ * The wavelength (in nm) is only set when rendering in spectral mode, in
+ * which case the ray carries the radiance of that single wavelength.
+ */
 pub struct Ray {
     pub origin: Array1<f64>,
     pub direction: Array1<f64>,
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
@@ -82,6 +87,7 @@ impl Ray {
         Ray {
             origin,
             direction: Vec4::normalize(direction),
+            wavelength: None,
         }
     }
 
@@ -142,6 +148,7 @@ mod tests {
         let ray = Ray {
             origin: arr1(&[0.5, 0.6, 0.7, 1.0]),
             direction: arr1(&[1.0, 1.0, 1.0, 0.0]),
+            wavelength: None,
         };
 
         assert_eq!(ray.origin[2], 0.7);
@@ -1,5 +1,14 @@
+use crate::raytracer::actor::RayTraceable;
+use crate::raytracer::actor::Sphere;
+use crate::raytracer::camera::Camera;
+use crate::raytracer::canvas::Canvas;
 use crate::raytracer::image::compute_buffer_difference;
 use crate::raytracer::image::Image;
+use crate::raytracer::material::Lambertian;
+use crate::raytracer::material::MaterialLibrary;
+use crate::raytracer::material::Primary;
+use crate::raytracer::material::Shading;
+use ndarray::{arr1, Array1};
 use std::fs::create_dir;
 use std::path::PathBuf;
 
@@ -25,6 +34,110 @@ pub fn init_image_testing() -> PathBuf {
     test_path
 }
 
+/**
+ * Grey diffuse sphere in front of a 90 degrees pinhole camera at the
+ * origin, optionally standing on a ground sphere.
+ */
+pub fn diffuse_sphere_scene(
+    width: u32,
+    height: u32,
+    samples: u32,
+    ground: bool,
+) -> Canvas {
+    let mut materials = MaterialLibrary::new();
+    let diffuse = materials.add(
+        "diffuse",
+        Box::new(Lambertian::new(arr1(&[0.5, 0.5, 0.5, 1.0]), Shading::COLOR)),
+    );
+    let mut actors: Vec<Box<dyn RayTraceable>> = vec![];
+    if ground {
+        actors.push(Box::new(Sphere::new(
+            arr1(&[0.0, -100.5, -1.0, 1.0]),
+            100.0,
+            diffuse,
+        )));
+    }
+    actors.push(Box::new(Sphere::new(
+        arr1(&[0.0, 0.0, -1.0, 1.0]),
+        0.5,
+        diffuse,
+    )));
+
+    let camera = pinhole_camera(width, height);
+    Canvas::new(width, height, actors, materials, samples, camera)
+}
+
+/**
+ * Red sphere shaded by its normals on a grey ground sphere (both unlit),
+ * in front of a 90 degrees pinhole camera at the origin.
+ */
+pub fn normals_sphere_scene(width: u32, height: u32, samples: u32) -> Canvas {
+    let mut materials = MaterialLibrary::new();
+    let actors = vec![
+        Box::new(Sphere::new(
+            arr1(&[0.0, 0.0, -1.0, 1.0]),
+            0.5,
+            materials.add(
+                "primary",
+                Box::new(Primary::new(
+                    arr1(&[1.0, 0.0, 0.0, 1.0]),
+                    Shading::NORMALS,
+                )),
+            ),
+        )) as Box<dyn RayTraceable>,
+        Box::new(Sphere::new(
+            arr1(&[0.0, -100.5, -1.0, 1.0]),
+            100.0,
+            materials.add(
+                "ground",
+                Box::new(Primary::new(
+                    arr1(&[0.5, 0.5, 0.5, 1.0]),
+                    Shading::COLOR,
+                )),
+            ),
+        )) as Box<dyn RayTraceable>,
+    ];
+
+    let camera = pinhole_camera(width, height);
+    Canvas::new(width, height, actors, materials, samples, camera)
+}
+
+/**
+ * Pinhole camera at the center of a large sphere of the (unlit) color, so
+ * that every pixel sees that color.
+ */
+pub fn flat_sphere_scene(
+    width: u32,
+    height: u32,
+    samples: u32,
+    color: Array1<f64>,
+) -> Canvas {
+    let mut materials = MaterialLibrary::new();
+    let flat =
+        materials.add("flat", Box::new(Primary::new(color, Shading::COLOR)));
+    let actors =
+        vec![
+            Box::new(Sphere::new(arr1(&[0.0, 0.0, 0.0, 1.0]), 10.0, flat))
+                as Box<dyn RayTraceable>,
+        ];
+
+    let camera = pinhole_camera(width, height);
+    Canvas::new(width, height, actors, materials, samples, camera)
+}
+
+/** 90 degrees pinhole camera at the origin, looking down -z. */
+fn pinhole_camera(width: u32, height: u32) -> Camera {
+    Camera::new(
+        90.0,
+        width,
+        height,
+        arr1(&[0.0, 0.0, 0.0, 1.0]),
+        arr1(&[0.0, 0.0, -1.0, 1.0]),
+        arr1(&[0.0, 1.0, 0.0, 0.0]),
+        0.0,
+    )
+}
+
 pub fn equals_to_baseline(image: Image, path: PathBuf, threshold: f32) {
     let mut diff_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     diff_path.push("testing/diff");
@@ -0,0 +1,262 @@
+use crate::raytracer::image::Image;
+use crate::raytracer::image::Pixel;
+
+/**
+ * Linear radiance of the pixels (RGBA), as rendered: unlike the 8-bit
+ * Image, values are not bounded (high dynamic range). Images are made from
+ * it as a separate step (see to_image).
+ */
+#[derive(Clone)]
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<Pixel<f64>>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        let size = width as usize * height as usize;
+        Film {
+            width,
+            height,
+            data: vec![
+                Pixel {
+                    data: [0.0, 0.0, 0.0, 0.0]
+                };
+                size
+            ],
+        }
+    }
+
+    pub fn get_value(&self, x: u32, y: u32, c: u32) -> f64 {
+        let index = y * self.width + x;
+
+        self.data[index as usize].data[c as usize]
+    }
+
+    /**
+     * 8-bit image of the film: the radiance is tone mapped to [0.0, 1.0]
+     * (NaN to 0.0), encoded with the sRGB transfer function and rounded to
+     * the nearest level. The image is opaque.
+     */
+    pub fn to_image(&self, tone_mapper: &ToneMapper) -> Image {
+        let mut image = Image::new(self.width, self.height, 4);
+        for (pixel, radiance) in image.data.iter_mut().zip(self.data.iter()) {
+            let [r, g, b, _alpha] = radiance.data;
+            let rgb = [r, g, b].map(|x| if x.is_nan() { 0.0 } else { x });
+            let [r, g, b] = tone_mapper.map(rgb).map(encode);
+            pixel.data = [r, g, b, 255];
+        }
+
+        image
+    }
+}
+
+/**
+ * Operator mapping (exposed) radiance to displayable values in [0.0, 1.0]:
+ * Clamp cuts it at the white point, Reinhard compresses all of it (1.0
+ * maps to 0.5), extended Reinhard and Filmic (Uncharted 2) compress it up
+ * to the white point, ACES (fitted) is the film-like response of the
+ * Academy Color Encoding System. All but ACES apply to each channel.
+ *
+ * References:
+ * * Reinhard et al., "Photographic Tone Reproduction for Digital Images",
+ *   SIGGRAPH 2002.
+ * * Hable, "Filmic Tonemapping Operators", 2010.
+ * * Hill, "ACES Fitted", BakingLab, 2016.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ToneMapping {
+    #[default]
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Filmic,
+    Aces,
+}
+
+/**
+ * Development of the film: the radiance is scaled by the exposure (in EV,
+ * each step doubles it) and tone mapped, the white point being the
+ * (exposed) radiance that maps to 1.0 (where the operator has one, Filmic
+ * is usually used with 11.2).
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapper {
+    pub tone_mapping: ToneMapping,
+    pub exposure: f64,
+    pub white_point: f64,
+}
+
+impl Default for ToneMapper {
+    fn default() -> ToneMapper {
+        ToneMapper {
+            tone_mapping: ToneMapping::Clamp,
+            exposure: 0.0,
+            white_point: 1.0,
+        }
+    }
+}
+
+impl ToneMapper {
+    pub fn new(tone_mapping: ToneMapping) -> ToneMapper {
+        ToneMapper {
+            tone_mapping,
+            ..ToneMapper::default()
+        }
+    }
+
+    /** Linear displayable color, in [0.0, 1.0], of the radiance. */
+    pub fn map(&self, rgb: [f64; 3]) -> [f64; 3] {
+        let rgb = rgb.map(|x| x.max(0.0) * 2.0_f64.powf(self.exposure));
+        let white = self.white_point;
+
+        let mapped = match self.tone_mapping {
+            ToneMapping::Clamp => rgb.map(|x| x / white),
+            ToneMapping::Reinhard => rgb.map(|x| x / (1.0 + x)),
+            ToneMapping::ExtendedReinhard => {
+                rgb.map(|x| x * (1.0 + x / (white * white)) / (1.0 + x))
+            }
+            ToneMapping::Filmic => {
+                rgb.map(|x| uncharted2(x) / uncharted2(white))
+            }
+            ToneMapping::Aces => aces_fitted(rgb),
+        };
+
+        mapped.map(|x| x.clamp(0.0, 1.0))
+    }
+}
+
+fn uncharted2(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+/**
+ * The RRT and ODT of ACES, fitted: from sRGB to the ACES space, the fit of
+ * the tone curve, and back.
+ */
+fn aces_fitted(rgb: [f64; 3]) -> [f64; 3] {
+    const INPUT: [[f64; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f64; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let multiply = |matrix: &[[f64; 3]; 3], v: [f64; 3]| {
+        matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+    };
+
+    let curve = multiply(&INPUT, rgb).map(|v| {
+        (v * (v + 0.0245786) - 0.000090537)
+            / (v * (0.983729 * v + 0.4329510) + 0.238081)
+    });
+    multiply(&OUTPUT, curve)
+}
+
+/** sRGB transfer function (OETF) of a linear value in [0.0, 1.0]. */
+pub fn srgb_encode(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn encode(value: f64) -> u8 {
+    (srgb_encode(value) * 255.0).round() as u8
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Tests
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn film_to_image() {
+        let mut film = Film::new(2, 1);
+        film.data[0].data = [4.0, 1.0, 0.25, 1.0];
+        film.data[1].data = [-1.0, f64::NAN, 0.5, 0.0];
+        let image = film.to_image(&ToneMapper::default());
+
+        // Out of range values are clamped, not wrapped around.
+        assert!(image.data[0].data == [255, 255, 137, 255]);
+        assert!(image.data[1].data == [0, 0, 188, 255]);
+        assert!(film.get_value(0, 0, 0) == 4.0);
+
+        // One more EV is twice the radiance.
+        let exposed = ToneMapper {
+            exposure: 1.0,
+            ..ToneMapper::default()
+        };
+        film.data[0].data = [0.125, 0.25, 0.5, 1.0];
+        assert!(film.to_image(&exposed).data[0].data == [137, 188, 255, 255]);
+    }
+
+    #[test]
+    fn film_srgb() {
+        // Both pieces meet, and the ends are fixed.
+        let knee = 0.0031308;
+        assert!((srgb_encode(knee) - 12.92 * knee).abs() < 1e-6);
+        assert!((srgb_encode(knee + 1e-9) - srgb_encode(knee)).abs() < 1e-6);
+        assert!(srgb_encode(0.0) == 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn film_tone_mapping() {
+        let operators = [
+            ToneMapping::Clamp,
+            ToneMapping::Reinhard,
+            ToneMapping::ExtendedReinhard,
+            ToneMapping::Filmic,
+            ToneMapping::Aces,
+        ];
+        for operator in operators.iter() {
+            let tone_mapper = ToneMapper {
+                white_point: 4.0,
+                ..ToneMapper::new(*operator)
+            };
+
+            // Black stays black, brighter is never darker, all in range.
+            let mut last = tone_mapper.map([0.0, 0.0, 0.0]);
+            assert!(last.iter().all(|x| x.abs() < 1e-3));
+            for step in 1..100 {
+                let x = step as f64 * 0.1;
+                let mapped = tone_mapper.map([x, x, x]);
+                for channel in 0..3 {
+                    assert!(mapped[channel] >= last[channel] - 1e-9);
+                    assert!((0.0..=1.0).contains(&mapped[channel]));
+                }
+                last = mapped;
+            }
+        }
+
+        // The white point maps to white (where the operator has one).
+        for operator in [
+            ToneMapping::Clamp,
+            ToneMapping::ExtendedReinhard,
+            ToneMapping::Filmic,
+        ]
+        .iter()
+        {
+            let tone_mapper = ToneMapper {
+                white_point: 4.0,
+                ..ToneMapper::new(*operator)
+            };
+            assert!((tone_mapper.map([4.0, 4.0, 4.0])[0] - 1.0).abs() < 1e-9);
+            assert!(tone_mapper.map([2.0, 2.0, 2.0])[0] < 1.0);
+        }
+        let reinhard = ToneMapper::new(ToneMapping::Reinhard);
+        assert!((reinhard.map([1.0, 1.0, 1.0])[0] - 0.5).abs() < 1e-9);
+        let aces = ToneMapper::new(ToneMapping::Aces);
+        assert!(aces.map([100.0, 100.0, 100.0])[0] > 0.95);
+    }
+}
@@ -0,0 +1,163 @@
+use std::f64::consts::PI;
+
+/**
+ * Reconstruction filter of the pixel samples: every sample contributes to
+ * the pixels (centers) within the radius of it, weighted by the filter, and
+ * pixels are the weighted average of their contributions. The box filter
+ * with a radius of half a pixel is the plain average of the samples in the
+ * pixel, the sharpest. Wider ones trade sharpness for less aliasing and
+ * noise, less of it for the same radius with the negative lobes of Mitchell
+ * and Lanczos than with tent and Gaussian.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f64,
+}
+
+/**
+ * Shape of a filter, separable in x and y. The negative lobes of Mitchell
+ * and Lanczos sharpen the edges (with some ringing).
+ *
+ * References:
+ * * Mitchell, Netravali, "Reconstruction Filters in Computer Graphics",
+ *   SIGGRAPH 1988.
+ * * Pharr, Jakob, Humphreys, "Physically Based Rendering", 3rd edition,
+ *   section 7.8.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FilterKind {
+    #[default]
+    Box,
+    Tent,
+    Gaussian {
+        alpha: f64,
+    },
+    Mitchell {
+        b: f64,
+        c: f64,
+    },
+    Lanczos {
+        tau: f64,
+    },
+}
+
+impl Filter {
+    /** Filter of the kind, with its usual radius (in pixels). */
+    pub fn new(kind: FilterKind) -> Filter {
+        let radius = match kind {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian { .. } => 1.5,
+            FilterKind::Mitchell { .. } => 2.0,
+            FilterKind::Lanczos { .. } => 3.0,
+        };
+
+        Filter { kind, radius }
+    }
+
+    /**
+     * Pixels, on each side of the one of a sample, that the sample can
+     * contribute to.
+     */
+    pub fn extent(&self) -> i64 {
+        (self.radius - 0.5).ceil().max(0.0) as i64
+    }
+
+    /** Weight of a sample at (x, y) (in pixels) from a pixel center. */
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => self.radius - x,
+            FilterKind::Gaussian { alpha } => {
+                (-alpha * x * x).exp()
+                    - (-alpha * self.radius * self.radius).exp()
+            }
+            FilterKind::Mitchell { b, c } => {
+                let t = 2.0 * x / self.radius;
+                let value = if t < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * t * t * t
+                        + (-18.0 + 12.0 * b + 6.0 * c) * t * t
+                        + (6.0 - 2.0 * b)
+                } else {
+                    (-b - 6.0 * c) * t * t * t
+                        + (6.0 * b + 30.0 * c) * t * t
+                        + (-12.0 * b - 48.0 * c) * t
+                        + (8.0 * b + 24.0 * c)
+                };
+                value / 6.0
+            }
+            FilterKind::Lanczos { tau } => sinc(x) * sinc(x / tau),
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter::new(FilterKind::Box)
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+
+    (PI * x).sin() / (PI * x)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Tests
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_shapes() {
+        let mitchell = Filter::new(FilterKind::Mitchell {
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        });
+        let lanczos = Filter::new(FilterKind::Lanczos { tau: 3.0 });
+        let filters = [
+            Filter::default(),
+            Filter::new(FilterKind::Tent),
+            Filter::new(FilterKind::Gaussian { alpha: 2.0 }),
+            mitchell,
+            lanczos,
+        ];
+        for filter in filters.iter() {
+            // Peak at the center, symmetric, nothing beyond the radius.
+            let center = filter.evaluate(0.0, 0.0);
+            assert!(center > 0.0);
+            for x in [0.25, 0.5, 1.0, 1.7].iter() {
+                assert!(filter.evaluate(*x, 0.1) <= center);
+                assert!(filter.evaluate(*x, 0.1) == filter.evaluate(-*x, -0.1));
+            }
+            assert!(filter.evaluate(filter.radius + 0.01, 0.0) == 0.0);
+        }
+
+        // Box and Mitchell are the usual ones, Mitchell and Lanczos have
+        // negative lobes.
+        assert!(Filter::default().extent() == 0);
+        assert!(Filter::default().evaluate(0.5, -0.5) == 1.0);
+        assert!(
+            (mitchell.evaluate(0.0, 0.0) - (8.0_f64 / 9.0).powi(2)).abs()
+                < 1e-9
+        );
+        assert!(mitchell.evaluate(1.5, 0.0) < 0.0);
+        assert!(mitchell.extent() == 2);
+        assert!(lanczos.evaluate(1.5, 0.0) < 0.0);
+        assert!(lanczos.evaluate(1.0, 0.0).abs() < 1e-9);
+    }
+}
@@ -110,6 +110,21 @@ impl Image {
         self.data[index] = Pixel { data: color };
     }
 
+    /**
+     * Copy of the rectangle of pixels at (x, y), which must be within the
+     * image.
+     */
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Image {
+        let mut image = Image::new(width, height, self.chan);
+        for (index, pixel) in image.data.iter_mut().enumerate() {
+            let (crop_x, crop_y) = Image::pixel_coordinate(width, index);
+            let source = (y + crop_y) * self.width + x + crop_x;
+            *pixel = self.data[source as usize];
+        }
+
+        image
+    }
+
     pub fn as_flat_vec_u8(&self) -> Vec<u8> {
         self.data
             .iter()
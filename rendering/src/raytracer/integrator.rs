@@ -0,0 +1,1965 @@
+use crate::raytracer::actor::Hit;
+use crate::raytracer::actor::Hittable;
+use crate::raytracer::actor::HittableList;
+use crate::raytracer::camera::Camera;
+use crate::raytracer::common::Ray;
+use crate::raytracer::common::Vec4;
+use crate::raytracer::material::random_dir_unit_sphere;
+use crate::raytracer::material::random_unit_vector;
+use crate::raytracer::material::Medium;
+use crate::raytracer::photon::Photon;
+use crate::raytracer::photon::PhotonMap;
+use crate::raytracer::sampler;
+use crate::raytracer::sampler::MetropolisSampler;
+use crate::raytracer::sampler::RandomSampler;
+use crate::raytracer::sampler::Sampler;
+use crate::raytracer::spectrum;
+use ndarray::{arr1, s, Array1};
+use rayon::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/**
+ * Integrators estimate the radiance (RGBA) arriving along a camera ray,
+ * the Canvas averages their estimates over the samples of each pixel.
+ *
+ * In spectral mode, rays carry a wavelength and only the first channel of
+ * the estimate is used (see Canvas).
+ */
+pub trait Integrator: Sync + Send {
+    fn radiance(&self, world: &HittableList, ray: &Ray) -> Array1<f64>;
+
+    /**
+     * Called (by the Canvas) before rendering the world, e.g. to trace
+     * photons.
+     */
+    fn preprocess(&mut self, _world: &HittableList) {}
+
+    /**
+     * Renders the whole image (the resolution of the camera) at once, for
+     * integrators that do not estimate each pixel independently (e.g.
+     * Metropolis): the linear RGBA color of every pixel, row by row. None
+     * (the default) lets the Canvas average radiance estimates per pixel.
+     */
+    fn render(
+        &self,
+        _world: &HittableList,
+        _camera: &Camera,
+        _samples: u32,
+    ) -> Option<Vec<Array1<f64>>> {
+        None
+    }
+}
+
+/**
+ *  Compute the background color based on the ray direction.
+ *  Use LERP (linear interpolation), to generate a gradient on the
+ *  y-direction (similar to front-to-back blending).
+ */
+pub fn background_color(ray: &Ray) -> Array1<f64> {
+    let dir = Vec4::normalize(ray.direction.clone());
+    let param_y: f64 = 0.5 * (dir[1] + 1.0);
+
+    //let white = arr1(&[0.8, 0.8, 0.8, 0.9]);
+    //let blue = arr1(&[0.1, 0.2, 0.65, 0.9]);
+    let white = arr1(&[1.0, 1.0, 1.0, 1.0]);
+    let blue = arr1(&[0.5, 0.7, 1.0, 1.0]);
+    let color = (1.0 - param_y) * white + param_y * blue;
+
+    at_wavelength(color, ray)
+}
+
+/**
+ * Converts a color to the wavelength carried by the ray (spectral mode),
+ * otherwise returns it as is.
+ */
+fn at_wavelength(color: Array1<f64>, ray: &Ray) -> Array1<f64> {
+    match ray.wavelength {
+        Some(wavelength) => spectrum::at_wavelength(&color, wavelength),
+        None => color,
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Unidirectional path tracing, the full light transport.
+ *
+ * Paths are at most max_depth segments long (the camera ray included).
+ * After roulette_depth segments, paths are randomly terminated with a
+ * probability that grows as their throughput drops (Russian roulette),
+ * and the survivors are weighted up accordingly, which keeps the image
+ * unbiased while not wasting time on paths that barely contribute.
+ */
+#[derive(Clone)]
+pub struct PathTracer {
+    pub max_depth: u32,
+    pub roulette_depth: u32,
+}
+
+impl PathTracer {
+    pub fn new() -> PathTracer {
+        PathTracer {
+            max_depth: 50,
+            roulette_depth: 20,
+        }
+    }
+
+    /**
+     * Throughput is the product of the attenuations along the path so
+     * far (up to the camera), which drives the Russian roulette.
+     */
+    fn trace(
+        &self,
+        world: &HittableList,
+        ray: &Ray,
+        depth: u32,
+        throughput: &Array1<f64>,
+    ) -> Array1<f64> {
+        let current_hit = &mut Hit::new();
+
+        // Some of the reflected rays hit the object they are reflecting
+        // off of not at exactly t=0, but instead at t=-0.0000001 or
+        // t=0.00000001 or whatever floating point approximation the (sphere)
+        // intersector gives us. So we need to ignore hits very near zero and
+        // we do this by raising the minimum to 0.001.
+        if !world.is_hit(ray, 0.0001, f64::MAX, current_hit) {
+            return background_color(ray);
+        }
+
+        let mut attenuation = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        let mut scattered =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, 0.0, 0.0]));
+        let material = world.materials.get(current_hit.material);
+
+        if depth >= self.max_depth
+            || !material.scatter(
+                ray,
+                current_hit,
+                &mut attenuation,
+                &mut scattered,
+            )
+        {
+            return at_wavelength(material.color_noscatter(current_hit), ray);
+        }
+
+        scattered.wavelength = ray.wavelength;
+        let mut attenuation = at_wavelength(attenuation, ray);
+
+        let throughput = throughput * &attenuation;
+        if depth >= self.roulette_depth {
+            let survival = throughput
+                .slice(s![0..3])
+                .fold(0.0_f64, |max, x| max.max(*x))
+                .min(1.0);
+            if sampler::next_1d() >= survival {
+                return arr1(&[0.0, 0.0, 0.0, 1.0]);
+            }
+            attenuation /= survival;
+            attenuation[3] = 1.0;
+        }
+
+        // Scattered into an actor filled with a medium.
+        if let Some(medium) = material.medium() {
+            if scattered.direction.dot(&current_hit.normal) < 0.0 {
+                return attenuation
+                    * self.random_walk(
+                        world,
+                        scattered,
+                        medium,
+                        depth + 1,
+                        &throughput,
+                    );
+            }
+        }
+
+        attenuation * self.trace(world, &scattered, depth + 1, &throughput)
+    }
+
+    /**
+     * Random walk inside a (homogeneous) medium, starting with the ray
+     * that entered it. Free-flight distances are sampled with the
+     * extinction of a randomly chosen channel, and weighted with the
+     * average pdf of the three (spectral MIS), so that a single walk
+     * serves all of them. When the walk reaches the boundary, the
+     * surface of the actor handles the ray as usual (refraction out or
+     * internal reflection, which starts a new walk).
+     *
+     * Reference:
+     * * Wrenninge, Villemin, Hery, "Path Traced Subsurface Scattering
+     *   using Anisotropic Phase Functions and Non-Exponential Free
+     *   Flights", Pixar 2017.
+     */
+    fn random_walk(
+        &self,
+        world: &HittableList,
+        mut ray: Ray,
+        mut medium: Medium,
+        depth: u32,
+        path_throughput: &Array1<f64>,
+    ) -> Array1<f64> {
+        let max_steps = 256;
+        let mut throughput = arr1(&[1.0, 1.0, 1.0, 1.0]);
+
+        medium.albedo = at_wavelength(medium.albedo, &ray);
+        medium.extinction = at_wavelength(medium.extinction, &ray);
+
+        for _step in 0..max_steps {
+            let channel = sampler::gen_index(3);
+            let distance =
+                -(1.0 - sampler::next_1d()).ln() / medium.extinction[channel];
+
+            let boundary = &mut Hit::new();
+
+            if world.is_hit(&ray, 0.0001, distance, boundary) {
+                // Reached the boundary before scattering, with
+                // probability of the transmittance up to it.
+                let transmittance =
+                    (-boundary.t * &medium.extinction).mapv(f64::exp);
+                let pdf = transmittance.slice(s![0..3]).sum() / 3.0;
+
+                throughput = throughput * transmittance / pdf;
+                throughput[3] = 1.0;
+
+                let path_throughput = path_throughput * &throughput;
+                return throughput
+                    * self.trace(world, &ray, depth, &path_throughput);
+            }
+
+            let transmittance = (-distance * &medium.extinction).mapv(f64::exp);
+            let density = &medium.extinction * &transmittance;
+            let pdf = density.slice(s![0..3]).sum() / 3.0;
+            throughput = throughput * &medium.albedo * density / pdf;
+            throughput[3] = 1.0;
+
+            let wavelength = ray.wavelength;
+            ray = Ray::new(
+                ray.point_at_parameter(distance),
+                random_dir_unit_sphere(),
+            );
+            ray.wavelength = wavelength;
+        }
+
+        arr1(&[0.0, 0.0, 0.0, 1.0])
+    }
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        PathTracer::new()
+    }
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, world: &HittableList, ray: &Ray) -> Array1<f64> {
+        self.trace(world, ray, 1, &arr1(&[1.0, 1.0, 1.0, 1.0]))
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Direct lighting only, the light that reaches the camera after a single
+ * scattering event. Useful to tell direct from indirect (bounced) light.
+ *
+ * On diffuse surfaces (see Scattering::diffuse), it is estimated by
+ * sampling both a point on the lights (see HittableList::sample_light),
+ * through a shadow ray, and the BSDF, combined with multiple importance
+ * sampling (balance heuristic): the lights find small emitters, the BSDF
+ * large ones and the background. Other materials are sampled as they are
+ * (scatter), and gather what is emitted where the scattered ray lands.
+ * Emitters emit on the side their normal points to, as in
+ * BidirectionalPathTracer.
+ */
+#[derive(Clone)]
+pub struct DirectLighting {}
+
+impl DirectLighting {
+    pub fn new() -> DirectLighting {
+        DirectLighting {}
+    }
+
+    /**
+     * Light sampling estimate at the hit of a diffuse surface (of the
+     * reflectance), on the hemisphere around the normal.
+     */
+    fn sample_lights(
+        world: &HittableList,
+        ray: &Ray,
+        hit: &Hit,
+        normal: &Array1<f64>,
+        reflectance: &Array1<f64>,
+    ) -> Array1<f64> {
+        let black = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        let (light, pdf_area) = match world.sample_light() {
+            Some(sample) => sample,
+            None => return black,
+        };
+
+        let offset = &light.point - &hit.point;
+        let distance2 = Vec4::squared_length(offset.view());
+        let direction = &offset / distance2.sqrt();
+        let cos_surface = normal.dot(&direction);
+        let cos_light = -light.normal.dot(&direction);
+        if cos_surface <= 0.0
+            || cos_light <= 0.0
+            || world.is_occluded(
+                &Ray::new(hit.point.clone(), offset),
+                0.0001,
+                0.9999,
+            )
+        {
+            return black;
+        }
+
+        let emission = world.materials.get(light.material).emission(&light);
+        let emission = at_wavelength(emission.unwrap(), ray);
+        // Solid angle densities, of the lights and of the BSDF.
+        let pdf_light = pdf_area * distance2 / cos_light;
+        let pdf_bsdf = cos_surface / std::f64::consts::PI;
+
+        reflectance / std::f64::consts::PI
+            * emission
+            * (cos_surface / (pdf_light + pdf_bsdf))
+    }
+
+    /**
+     * BSDF sampling estimate at the hit of a diffuse surface (of the
+     * reflectance), on the hemisphere around the normal.
+     */
+    fn sample_bsdf(
+        world: &HittableList,
+        ray: &Ray,
+        hit: &Hit,
+        normal: &Array1<f64>,
+        reflectance: &Array1<f64>,
+    ) -> Array1<f64> {
+        let black = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        let direction = cosine_direction(normal);
+        let pdf_bsdf = normal.dot(&direction) / std::f64::consts::PI;
+        let mut scattered = Ray::new(hit.point.clone(), direction.clone());
+        scattered.wavelength = ray.wavelength;
+
+        // The background is only found this way.
+        let light = &mut Hit::new();
+        if !world.is_hit(&scattered, 0.0001, f64::MAX, light) {
+            return reflectance * &background_color(&scattered);
+        }
+
+        let cos_light = -light.normal.dot(&direction);
+        let emission = match world.materials.get(light.material).emission(light)
+        {
+            Some(emission) if cos_light > 0.0 => emission,
+            _ => return black,
+        };
+        let area = world.light_area();
+        let pdf_light = if area > 0.0 {
+            light.t * light.t / (cos_light * area)
+        } else {
+            0.0
+        };
+
+        reflectance
+            * &at_wavelength(emission, ray)
+            * (pdf_bsdf / (pdf_bsdf + pdf_light))
+    }
+}
+
+impl Default for DirectLighting {
+    fn default() -> Self {
+        DirectLighting::new()
+    }
+}
+
+impl Integrator for DirectLighting {
+    fn radiance(&self, world: &HittableList, ray: &Ray) -> Array1<f64> {
+        let hit = &mut Hit::new();
+        if !world.is_hit(ray, 0.0001, f64::MAX, hit) {
+            return background_color(ray);
+        }
+
+        let material = world.materials.get(hit.material);
+        if let Some(emission) = material.emission(hit) {
+            return at_wavelength(emission, ray);
+        }
+
+        if let Some(reflectance) = material.diffuse(hit) {
+            let reflectance = at_wavelength(reflectance, ray);
+            // Hemisphere on the side the ray comes from.
+            let mut normal = hit.normal.clone();
+            if normal.dot(&ray.direction) > 0.0 {
+                normal = -normal;
+            }
+
+            let mut color =
+                Self::sample_lights(world, ray, hit, &normal, &reflectance)
+                    + Self::sample_bsdf(world, ray, hit, &normal, &reflectance);
+            color[3] = 1.0;
+            return color;
+        }
+
+        let mut attenuation = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        let mut scattered =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, 0.0, 0.0]));
+        if !material.scatter(ray, hit, &mut attenuation, &mut scattered) {
+            return at_wavelength(material.color_noscatter(hit), ray);
+        }
+        scattered.wavelength = ray.wavelength;
+        let attenuation = at_wavelength(attenuation, ray);
+
+        let light = &mut Hit::new();
+        if !world.is_hit(&scattered, 0.0001, f64::MAX, light) {
+            return attenuation * background_color(&scattered);
+        }
+        match world.materials.get(light.material).emission(light) {
+            Some(emission) if light.normal.dot(&scattered.direction) < 0.0 => {
+                attenuation * at_wavelength(emission, &scattered)
+            }
+            _ => arr1(&[0.0, 0.0, 0.0, 1.0]),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Ambient occlusion, the fraction of the (cosine weighted) hemisphere
+ * above the first hit that is not occluded within the given distance.
+ * Misses are fully unoccluded (white).
+ */
+#[derive(Clone)]
+pub struct AmbientOcclusion {
+    pub distance: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(distance: f64) -> AmbientOcclusion {
+        AmbientOcclusion { distance }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, world: &HittableList, ray: &Ray) -> Array1<f64> {
+        let hit = &mut Hit::new();
+        if !world.is_hit(ray, 0.0001, f64::MAX, hit) {
+            return arr1(&[1.0, 1.0, 1.0, 1.0]);
+        }
+
+        // Hemisphere on the side the ray comes from.
+        let mut normal = hit.normal.clone();
+        if normal.dot(&ray.direction) > 0.0 {
+            normal = -normal;
+        }
+        let mut direction = &normal + &random_unit_vector();
+        if Vec4::squared_length(direction.view()) < 1e-12 {
+            direction = normal;
+        }
+
+        // Unit direction, so that distance is measured in scene units.
+        let occlusion_ray =
+            Ray::new(hit.point.clone(), Vec4::normalize(direction));
+        if world.is_occluded(&occlusion_ray, 0.0001, self.distance) {
+            arr1(&[0.0, 0.0, 0.0, 1.0])
+        } else {
+            arr1(&[1.0, 1.0, 1.0, 1.0])
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Outputs of the DebugView integrator, for the first hit of camera rays
+ * (misses are black, unless noted otherwise):
+ *
+ * * Normals: (x, y, z) mapped from [-1.0, 1.0] to [0.0, 1.0].
+ * * Depth: distance to the hit, from black (0.0) to white (max_distance).
+ * * Uv: surface coordinates as (u, v, 0.0).
+ * * MaterialId: a distinct color per material of the library.
+ * * Barycentrics: triangle barycentric coordinates. Only meaningful once
+ *   triangle meshes exist: spheres, the only actors so far, have none, so
+ *   it is black for now.
+ * * TraversalCost: number of actor intersection tests done to find the
+ *   hit (misses included), from blue (none) over green to red (max_cost).
+ */
+#[derive(Clone)]
+pub enum DebugOutput {
+    Normals,
+    Depth { max_distance: f64 },
+    Uv,
+    MaterialId,
+    Barycentrics,
+    TraversalCost { max_cost: f64 },
+}
+
+/**
+ * Renders surface information instead of light (see DebugOutput).
+ */
+#[derive(Clone)]
+pub struct DebugView {
+    pub output: DebugOutput,
+}
+
+impl DebugView {
+    pub fn new(output: DebugOutput) -> DebugView {
+        DebugView { output }
+    }
+}
+
+/**
+ * Fully saturated hue in [0.0, 1.0) to RGB.
+ */
+pub fn hue_to_rgb(hue: f64) -> Array1<f64> {
+    let h = hue.fract() * 6.0;
+
+    arr1(&[
+        ((h - 3.0).abs() - 1.0).clamp(0.0, 1.0),
+        (2.0 - (h - 2.0).abs()).clamp(0.0, 1.0),
+        (2.0 - (h - 4.0).abs()).clamp(0.0, 1.0),
+        1.0,
+    ])
+}
+
+impl Integrator for DebugView {
+    fn radiance(&self, world: &HittableList, ray: &Ray) -> Array1<f64> {
+        let black = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        let hit = &mut Hit::new();
+
+        if let DebugOutput::TraversalCost { max_cost } = self.output {
+            let cost = world.traversal_cost(ray, 0.0001, f64::MAX) as f64;
+
+            // From blue (hue 2/3) to red (hue 0).
+            return hue_to_rgb((1.0 - (cost / max_cost).min(1.0)) * 2.0 / 3.0);
+        }
+
+        if !world.is_hit(ray, 0.0001, f64::MAX, hit) {
+            return black;
+        }
+
+        match self.output {
+            DebugOutput::Normals => {
+                let mut color = (&hit.normal + 1.0) * 0.5;
+                color[3] = 1.0;
+                color
+            }
+            DebugOutput::Depth { max_distance } => {
+                let distance = hit.t * Vec4::l2_norm(ray.direction.view());
+                let value = (distance / max_distance).min(1.0);
+                arr1(&[value, value, value, 1.0])
+            }
+            DebugOutput::Uv => arr1(&[hit.uv[0], hit.uv[1], 0.0, 1.0]),
+            DebugOutput::MaterialId => {
+                // Golden ratio steps spread consecutive ids over the hues.
+                hue_to_rgb((hit.material.0 as f64 * 0.618_033_988_75).fract())
+            }
+            // No actor has barycentric coordinates until meshes exist.
+            DebugOutput::Barycentrics => black,
+            DebugOutput::TraversalCost { .. } => black,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Bidirectional path tracing: a camera subpath and a light subpath are
+ * traced, and every prefix of the one is connected to every prefix of the
+ * other (through a shadow ray), so that each path is built with all the
+ * strategies able to sample it. Their estimates are combined with
+ * multiple importance sampling (balance heuristic), which keeps the best
+ * of each: light that is hard to find from the camera (small lights,
+ * small openings) is easy to find from the lights.
+ *
+ * Paths are at most max_depth segments long (the camera ray included).
+ * Only diffuse materials (see Scattering::diffuse) can be connected, the
+ * others are sampled as they are (scatter) and treated as specular. Light
+ * subpaths start on the emitting actors (uniformly over their area, see
+ * HittableList), which emit on the side their normal points to. The
+ * background is only gathered by camera subpaths, and light subpaths are
+ * not connected to the camera (a sample only contributes to its own
+ * pixel). Media are not supported.
+ *
+ * References:
+ * * Veach, "Robust Monte Carlo Methods for Light Transport Simulation",
+ *   PhD thesis, Stanford 1997, chapter 10.
+ * * Pharr, Jakob, Humphreys, "Physically Based Rendering", 3rd edition,
+ *   section 16.3.
+ */
+#[derive(Clone)]
+pub struct BidirectionalPathTracer {
+    pub max_depth: u32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+}
+
+/**
+ * Beta is the throughput of the subpath up to the vertex. pdf_fwd is the
+ * (area) density of sampling the vertex from the previous one of its
+ * subpath, pdf_rev from the next one (as if the path was traced in the
+ * opposite direction). Delta vertices cannot be connected.
+ */
+struct Vertex {
+    kind: VertexKind,
+    hit: Hit,
+    beta: Array1<f64>,
+    pdf_fwd: f64,
+    pdf_rev: f64,
+    delta: bool,
+    reflectance: Option<Array1<f64>>,
+    emission: Option<Array1<f64>>,
+}
+
+impl Vertex {
+    fn camera(ray: &Ray) -> Vertex {
+        let mut hit = Hit::new();
+        hit.point = ray.origin.clone();
+        hit.normal = arr1(&[0.0, 0.0, 0.0, 0.0]);
+
+        Vertex {
+            kind: VertexKind::Camera,
+            hit,
+            beta: arr1(&[1.0, 1.0, 1.0, 1.0]),
+            pdf_fwd: 1.0,
+            pdf_rev: 0.0,
+            delta: false,
+            reflectance: None,
+            emission: None,
+        }
+    }
+
+    fn light(hit: Hit, emission: Array1<f64>, pdf: f64) -> Vertex {
+        Vertex {
+            kind: VertexKind::Light,
+            hit,
+            beta: emission.clone(),
+            pdf_fwd: pdf,
+            pdf_rev: 0.0,
+            delta: false,
+            reflectance: None,
+            emission: Some(emission),
+        }
+    }
+
+    fn surface(
+        hit: Hit,
+        beta: Array1<f64>,
+        reflectance: Option<Array1<f64>>,
+        emission: Option<Array1<f64>>,
+    ) -> Vertex {
+        Vertex {
+            kind: VertexKind::Surface,
+            hit,
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: reflectance.is_none(),
+            reflectance,
+            emission,
+        }
+    }
+
+    /**
+     * Unit direction towards the other vertex, and the squared distance.
+     */
+    fn towards(&self, other: &Vertex) -> (Array1<f64>, f64) {
+        let offset = &other.hit.point - &self.hit.point;
+        let distance2 = Vec4::squared_length(offset.view());
+        (offset / distance2.sqrt(), distance2)
+    }
+
+    /**
+     * Diffuse BSDF, for light arriving from prev and leaving towards next
+     * (reflection only).
+     */
+    fn f(&self, prev: &Vertex, next: &Vertex) -> Array1<f64> {
+        let black = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        let reflectance = match &self.reflectance {
+            Some(reflectance) => reflectance,
+            None => return black,
+        };
+
+        let (incoming, _) = self.towards(prev);
+        let (outgoing, _) = self.towards(next);
+        if self.hit.normal.dot(&incoming) * self.hit.normal.dot(&outgoing)
+            <= 0.0
+        {
+            return black;
+        }
+        reflectance / std::f64::consts::PI
+    }
+
+    /**
+     * Density (area) of sampling next from this vertex, reached from prev.
+     */
+    fn pdf(&self, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        if self.kind == VertexKind::Light {
+            return self.pdf_light(next);
+        }
+
+        let prev = prev.unwrap();
+        let (incoming, _) = self.towards(prev);
+        let (outgoing, _) = self.towards(next);
+        let cos_in = self.hit.normal.dot(&incoming);
+        let cos_out = self.hit.normal.dot(&outgoing);
+        if self.reflectance.is_none() || cos_in * cos_out <= 0.0 {
+            return 0.0;
+        }
+        self.convert_density(cos_out.abs() / std::f64::consts::PI, next)
+    }
+
+    /**
+     * Density (area) of next being reached by light emitted here.
+     */
+    fn pdf_light(&self, next: &Vertex) -> f64 {
+        let (direction, _) = self.towards(next);
+        let cosine = self.hit.normal.dot(&direction).max(0.0);
+        self.convert_density(cosine / std::f64::consts::PI, next)
+    }
+
+    /**
+     * Solid angle density (around this vertex) to area density at next.
+     */
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let (direction, distance2) = self.towards(next);
+        match next.kind {
+            VertexKind::Camera => pdf / distance2,
+            _ => pdf * next.hit.normal.dot(&direction).abs() / distance2,
+        }
+    }
+
+    /**
+     * Radiance emitted towards the other vertex.
+     */
+    fn emitted(&self, towards: &Vertex) -> Option<Array1<f64>> {
+        let (direction, _) = self.towards(towards);
+        match &self.emission {
+            Some(emission) if self.hit.normal.dot(&direction) > 0.0 => {
+                Some(emission.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Cosine weighted direction around the (unit) normal, the normal plus a
+ * uniformly distributed unit vector.
+ */
+fn cosine_direction(normal: &Array1<f64>) -> Array1<f64> {
+    loop {
+        let direction = normal + &random_unit_vector();
+        if Vec4::squared_length(direction.view()) > 1e-12 {
+            return Vec4::normalize(direction);
+        }
+    }
+}
+
+impl BidirectionalPathTracer {
+    pub fn new() -> BidirectionalPathTracer {
+        BidirectionalPathTracer { max_depth: 10 }
+    }
+
+    /**
+     * Extends the subpath with the vertices hit by the ray (sampled with
+     * the solid angle density pdf_dir), up to max_vertices. Returns the
+     * background it gathers when it escapes the scene.
+     */
+    fn random_walk(
+        world: &HittableList,
+        mut ray: Ray,
+        mut beta: Array1<f64>,
+        mut pdf_dir: f64,
+        max_vertices: usize,
+        path: &mut Vec<Vertex>,
+    ) -> Option<Array1<f64>> {
+        while path.len() < max_vertices {
+            let mut hit = Hit::new();
+            if !world.is_hit(&ray, 0.0001, f64::MAX, &mut hit) {
+                return Some(beta * background_color(&ray));
+            }
+
+            let material = world.materials.get(hit.material);
+            let reflectance = material
+                .diffuse(&hit)
+                .map(|color| at_wavelength(color, &ray));
+            let emission = material
+                .emission(&hit)
+                .map(|color| at_wavelength(color, &ray));
+
+            let mut vertex =
+                Vertex::surface(hit, beta.clone(), reflectance, emission);
+            vertex.pdf_fwd =
+                path.last().unwrap().convert_density(pdf_dir, &vertex);
+            path.push(vertex);
+            if path.len() >= max_vertices {
+                break;
+            }
+
+            let current = path.last().unwrap();
+            let outgoing = -Vec4::normalize(ray.direction.clone());
+            let (direction, pdf_rev) = match &current.reflectance {
+                Some(reflectance) => {
+                    // Hemisphere on the side the ray comes from.
+                    let mut normal = current.hit.normal.clone();
+                    if normal.dot(&outgoing) < 0.0 {
+                        normal = -normal;
+                    }
+                    let direction = cosine_direction(&normal);
+
+                    pdf_dir = normal.dot(&direction) / std::f64::consts::PI;
+                    beta *= reflectance;
+                    (direction, normal.dot(&outgoing) / std::f64::consts::PI)
+                }
+                None => {
+                    let mut attenuation = arr1(&[0.0, 0.0, 0.0, 1.0]);
+                    let mut scattered = Ray::new(
+                        arr1(&[0.0, 0.0, 0.0, 1.0]),
+                        arr1(&[0.0, 0.0, 0.0, 0.0]),
+                    );
+                    if !material.scatter(
+                        &ray,
+                        &current.hit,
+                        &mut attenuation,
+                        &mut scattered,
+                    ) {
+                        break;
+                    }
+
+                    pdf_dir = 0.0;
+                    beta = beta * at_wavelength(attenuation, &ray);
+                    (Vec4::normalize(scattered.direction), 0.0)
+                }
+            };
+            beta[3] = 1.0;
+
+            let last = path.len() - 1;
+            path[last - 1].pdf_rev =
+                path[last].convert_density(pdf_rev, &path[last - 1]);
+
+            let wavelength = ray.wavelength;
+            ray = Ray::new(path[last].hit.point.clone(), direction);
+            ray.wavelength = wavelength;
+        }
+
+        None
+    }
+
+    /**
+     * Contribution of the path made of the first s vertices of the light
+     * subpath and the first t of the camera subpath, MIS weighted. With
+     * s = 1, a new point on the lights is sampled instead.
+     */
+    fn connect(
+        world: &HittableList,
+        ray: &Ray,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        s: usize,
+        t: usize,
+    ) -> Array1<f64> {
+        let black = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        let pt = &camera_path[t - 1];
+        let pt_minus = &camera_path[t - 2];
+        let mut sampled = None;
+
+        let contribution = if s == 0 {
+            match pt.emitted(pt_minus) {
+                Some(emission) => &pt.beta * &emission,
+                None => return black,
+            }
+        } else if pt.reflectance.is_none() {
+            return black;
+        } else if s == 1 {
+            let (hit, pdf) = match world.sample_light() {
+                Some(sample) => sample,
+                None => return black,
+            };
+            let emission = world.materials.get(hit.material).emission(&hit);
+            let emission = at_wavelength(emission.unwrap(), ray);
+            let light = Vertex::light(hit, emission, pdf);
+
+            let contribution = match light.emitted(pt) {
+                Some(emission) => {
+                    &pt.beta
+                        * &pt.f(pt_minus, &light)
+                        * emission
+                        * (Self::geometry(world, pt, &light) / pdf)
+                }
+                None => return black,
+            };
+            sampled = Some(light);
+            contribution
+        } else {
+            let qs = &light_path[s - 1];
+            if qs.reflectance.is_none() {
+                return black;
+            }
+            &qs.beta
+                * &qs.f(&light_path[s - 2], pt)
+                * &pt.f(pt_minus, qs)
+                * &pt.beta
+                * Self::geometry(world, qs, pt)
+        };
+
+        if contribution.slice(s![0..3]).iter().all(|x| *x == 0.0) {
+            return black;
+        }
+        contribution
+            * Self::mis_weight(
+                world,
+                light_path,
+                camera_path,
+                sampled.as_ref(),
+                s,
+                t,
+            )
+    }
+
+    /**
+     * Geometric term between two vertices, 0.0 when they cannot see each
+     * other.
+     */
+    fn geometry(world: &HittableList, a: &Vertex, b: &Vertex) -> f64 {
+        let (direction, distance2) = a.towards(b);
+        let distance = distance2.sqrt();
+        let shadow = Ray::new(a.hit.point.clone(), direction.clone());
+        if world.is_occluded(&shadow, 0.0001, distance - 0.0001) {
+            return 0.0;
+        }
+
+        let cos_a = match a.kind {
+            VertexKind::Camera => 1.0,
+            _ => a.hit.normal.dot(&direction).abs(),
+        };
+        cos_a * b.hit.normal.dot(&direction).abs() / distance2
+    }
+
+    /**
+     * Balance heuristic weight of the (s, t) strategy: the inverse of the
+     * sum of the pdf ratios of all the strategies sampling the same path
+     * to its own (computed incrementally along the path, as in PBRT).
+     * Strategies connecting to the camera (t = 1) are not used, and left
+     * out.
+     */
+    fn mis_weight(
+        world: &HittableList,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        sampled: Option<&Vertex>,
+        s: usize,
+        t: usize,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let remap = |pdf: f64| if pdf == 0.0 { 1.0 } else { pdf };
+
+        // (pdf_fwd, pdf_rev, delta), updated for the strategy.
+        let mut light: Vec<(f64, f64, bool)> = light_path[..s]
+            .iter()
+            .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+            .collect();
+        let mut camera: Vec<(f64, f64, bool)> = camera_path[..t]
+            .iter()
+            .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+            .collect();
+
+        let qs = match s {
+            0 => None,
+            1 => sampled,
+            _ => Some(&light_path[s - 1]),
+        };
+        let qs_minus = if s > 1 {
+            Some(&light_path[s - 2])
+        } else {
+            None
+        };
+        let pt = &camera_path[t - 1];
+        let pt_minus = &camera_path[t - 2];
+
+        camera[t - 1].2 = false;
+        match qs {
+            Some(qs) => {
+                camera[t - 1].1 = qs.pdf(qs_minus, pt);
+                camera[t - 2].1 = pt.pdf(Some(qs), pt_minus);
+
+                light[s - 1] = (qs.pdf_fwd, pt.pdf(Some(pt_minus), qs), false);
+                if let Some(qs_minus) = qs_minus {
+                    light[s - 2].1 = qs.pdf(Some(pt), qs_minus);
+                }
+            }
+            None => {
+                let area = world.light_area();
+                camera[t - 1].1 = if area > 0.0 { 1.0 / area } else { 0.0 };
+                camera[t - 2].1 = pt.pdf_light(pt_minus);
+            }
+        }
+
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (2..t).rev() {
+            ratio *= remap(camera[i].1) / remap(camera[i].0);
+            if !camera[i].2 && !camera[i - 1].2 {
+                sum += ratio;
+            }
+        }
+
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].1) / remap(light[i].0);
+            let delta_before = i > 0 && light[i - 1].2;
+            if !light[i].2 && !delta_before {
+                sum += ratio;
+            }
+        }
+
+        1.0 / (1.0 + sum)
+    }
+}
+
+impl Default for BidirectionalPathTracer {
+    fn default() -> Self {
+        BidirectionalPathTracer::new()
+    }
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn radiance(&self, world: &HittableList, ray: &Ray) -> Array1<f64> {
+        let max_depth = self.max_depth as usize;
+
+        let mut camera_ray =
+            Ray::new(ray.origin.clone(), ray.direction.clone());
+        camera_ray.wavelength = ray.wavelength;
+
+        let mut camera_path = vec![Vertex::camera(ray)];
+        let mut radiance = Self::random_walk(
+            world,
+            camera_ray,
+            arr1(&[1.0, 1.0, 1.0, 1.0]),
+            1.0,
+            max_depth + 1,
+            &mut camera_path,
+        )
+        .unwrap_or_else(|| arr1(&[0.0, 0.0, 0.0, 1.0]));
+
+        let mut light_path = Vec::with_capacity(max_depth);
+        if let Some((hit, pdf)) = world.sample_light() {
+            let emission = world.materials.get(hit.material).emission(&hit);
+            let emission = at_wavelength(emission.unwrap(), ray);
+            let direction = cosine_direction(&hit.normal);
+            let pdf_dir = hit.normal.dot(&direction) / std::f64::consts::PI;
+
+            let mut light_ray = Ray::new(hit.point.clone(), direction);
+            light_ray.wavelength = ray.wavelength;
+
+            // Le cos / (pdf pdf_dir), with pdf_dir = cos / PI.
+            let beta = &emission * (std::f64::consts::PI / pdf);
+            light_path.push(Vertex::light(hit, emission, pdf));
+            Self::random_walk(
+                world,
+                light_ray,
+                beta,
+                pdf_dir,
+                max_depth - 1,
+                &mut light_path,
+            );
+        }
+
+        for t in 2..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                if s + t - 1 > max_depth {
+                    break;
+                }
+                radiance = radiance
+                    + Self::connect(
+                        world,
+                        ray,
+                        &light_path,
+                        &camera_path,
+                        s,
+                        t,
+                    );
+            }
+        }
+
+        radiance[3] = 1.0;
+        radiance
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Path tracing, with the caustics (light focused by specular surfaces onto
+ * diffuse ones, e.g. below glass spheres) from a photon map, as camera
+ * paths hardly ever find them: before rendering, photons are shot from
+ * the lights and stored where they land on a diffuse surface after one or
+ * more specular bounces. At every diffuse hit of a camera path, the
+ * caustics are estimated from the density of the nearest photons (within
+ * max_radius), and the path is not counted when it reaches a light
+ * through specular surfaces only from there (the same light).
+ *
+ * Photons are emitted from the emitting actors (see HittableList) and
+ * from the background, aimed at the specular actors: only photons that
+ * hit those first can end up in the map. Diffuse and specular materials
+ * are as for the bidirectional path tracer. Photons carry RGB power (also
+ * in spectral mode), and media are not supported.
+ *
+ * Reference:
+ * * Jensen, "Realistic Image Synthesis Using Photon Mapping", 2001.
+ */
+#[derive(Clone)]
+pub struct PhotonMapper {
+    pub photons: usize,
+    pub nearest: usize,
+    pub max_radius: f64,
+    pub max_depth: u32,
+    map: PhotonMap,
+}
+
+/**
+ * Surfaces a camera path went through: specular ones only (from the
+ * camera), a diffuse one last, or a diffuse one and specular ones since.
+ */
+#[derive(Clone, Copy, PartialEq)]
+enum Chain {
+    Specular,
+    Diffuse,
+    Caustic,
+}
+
+impl PhotonMapper {
+    pub fn new() -> PhotonMapper {
+        PhotonMapper {
+            photons: 100_000,
+            nearest: 50,
+            max_radius: 0.1,
+            max_depth: 50,
+            map: PhotonMap::default(),
+        }
+    }
+
+    pub fn photon_map(&self) -> &PhotonMap {
+        &self.map
+    }
+
+    /**
+     * Bounding spheres (center, radius) of the specular actors.
+     */
+    fn specular_targets(world: &HittableList) -> Vec<(Array1<f64>, f64)> {
+        world
+            .actors
+            .iter()
+            .filter(|actor| match actor.sample_surface() {
+                Some(hit) => {
+                    let material = world.materials.get(hit.material);
+                    material.diffuse(&hit).is_none()
+                        && material.emission(&hit).is_none()
+                }
+                None => false,
+            })
+            .filter_map(|actor| actor.bounding_box(0.0, 0.0))
+            .map(|aabb| {
+                let center = (aabb.min() + aabb.max()) * 0.5;
+                let radius =
+                    Vec4::l2_norm((aabb.max() - aabb.min()).view()) * 0.5;
+                (center, radius)
+            })
+            .collect()
+    }
+
+    /**
+     * Photon from the background, aimed at one of the targets: through a
+     * disk covering it, perpendicular to the direction, from outside the
+     * world. Its density is that of all the disks it goes through, any of
+     * them could have been picked.
+     */
+    fn background_photon(
+        targets: &[(Array1<f64>, f64)],
+        distance: f64,
+    ) -> (Ray, Array1<f64>, f64) {
+        let direction = random_unit_vector();
+        let (center, radius) = &targets[sampler::gen_index(targets.len())];
+
+        let helper = if direction[0].abs() > 0.9 {
+            arr1(&[0.0, 1.0, 0.0, 0.0])
+        } else {
+            arr1(&[1.0, 0.0, 0.0, 0.0])
+        };
+        let e1 = Vec4::normalize(Vec4::cross(direction.clone(), helper));
+        let e2 = Vec4::cross(direction.clone(), e1.clone());
+        let r = radius * sampler::next_1d().sqrt();
+        let phi = 2.0 * std::f64::consts::PI * sampler::next_1d();
+        let point = center + &(e1 * (r * phi.cos()) + e2 * (r * phi.sin()));
+        let origin = &point - &(&direction * distance);
+
+        let mut pdf = 0.0;
+        for (center, radius) in targets.iter() {
+            let offset = center - &origin;
+            let along = offset.dot(&direction);
+            if offset.dot(&offset) - along * along < radius * radius {
+                pdf += 1.0 / (std::f64::consts::PI * radius * radius);
+            }
+        }
+        pdf /= targets.len() as f64 * 4.0 * std::f64::consts::PI;
+
+        let sky = background_color(&Ray::new(point, -&direction));
+        (Ray::new(origin, direction), sky, pdf)
+    }
+
+    /**
+     * Follows the photon through the specular surfaces it hits, and
+     * stores it on the first diffuse one (after at least one of them).
+     */
+    fn trace_photon(
+        &self,
+        world: &HittableList,
+        mut ray: Ray,
+        mut power: Array1<f64>,
+        stored: &mut Vec<Photon>,
+    ) {
+        let mut specular = false;
+        for _depth in 0..self.max_depth {
+            let mut hit = Hit::new();
+            if !world.is_hit(&ray, 0.0001, f64::MAX, &mut hit) {
+                return;
+            }
+
+            let material = world.materials.get(hit.material);
+            if material.diffuse(&hit).is_some() {
+                if specular {
+                    let direction = Vec4::normalize(ray.direction);
+                    stored.push(Photon::new(hit.point, direction, power));
+                }
+                return;
+            }
+
+            let mut attenuation = arr1(&[0.0, 0.0, 0.0, 1.0]);
+            let mut scattered = Ray::new(
+                arr1(&[0.0, 0.0, 0.0, 1.0]),
+                arr1(&[0.0, 0.0, 0.0, 0.0]),
+            );
+            if !material.scatter(&ray, &hit, &mut attenuation, &mut scattered) {
+                return;
+            }
+            power = power * attenuation;
+            power[3] = 1.0;
+            specular = true;
+            ray = scattered;
+        }
+    }
+
+    /**
+     * Radiance of the caustics leaving the (diffuse) hit towards the ray
+     * origin: the power of the nearest photons (arriving on that side),
+     * over the area they are found in, reflected by the BSDF.
+     */
+    fn caustics(
+        &self,
+        hit: &Hit,
+        reflectance: &Array1<f64>,
+        ray: &Ray,
+    ) -> Array1<f64> {
+        let mut normal = hit.normal.clone();
+        if normal.dot(&ray.direction) > 0.0 {
+            normal = -normal;
+        }
+
+        let (photons, radius2) =
+            self.map.nearest(&hit.point, self.nearest, self.max_radius);
+        let mut flux = arr1(&[0.0, 0.0, 0.0, 0.0]);
+        for photon in photons.iter() {
+            if photon.direction.dot(&normal) < 0.0 {
+                flux += &photon.power;
+            }
+        }
+
+        let pi = std::f64::consts::PI;
+        let mut radiance = flux * reflectance / (pi * pi * radius2);
+        radiance[3] = 1.0;
+        at_wavelength(radiance, ray)
+    }
+
+    fn trace(
+        &self,
+        world: &HittableList,
+        ray: &Ray,
+        depth: u32,
+        chain: Chain,
+    ) -> Array1<f64> {
+        let black = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        let hit = &mut Hit::new();
+        if !world.is_hit(ray, 0.0001, f64::MAX, hit) {
+            if chain == Chain::Caustic {
+                return black;
+            }
+            return background_color(ray);
+        }
+
+        let mut attenuation = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        let mut scattered =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, 0.0, 0.0]));
+        let material = world.materials.get(hit.material);
+
+        if depth >= self.max_depth
+            || !material.scatter(ray, hit, &mut attenuation, &mut scattered)
+        {
+            if chain == Chain::Caustic {
+                return black;
+            }
+            return at_wavelength(material.color_noscatter(hit), ray);
+        }
+        scattered.wavelength = ray.wavelength;
+        let attenuation = at_wavelength(attenuation, ray);
+
+        match material.diffuse(hit) {
+            Some(reflectance) => {
+                self.caustics(hit, &reflectance, ray)
+                    + attenuation
+                        * self.trace(
+                            world,
+                            &scattered,
+                            depth + 1,
+                            Chain::Diffuse,
+                        )
+            }
+            None => {
+                let chain = match chain {
+                    Chain::Specular => Chain::Specular,
+                    _ => Chain::Caustic,
+                };
+                attenuation * self.trace(world, &scattered, depth + 1, chain)
+            }
+        }
+    }
+}
+
+impl Default for PhotonMapper {
+    fn default() -> Self {
+        PhotonMapper::new()
+    }
+}
+
+impl Integrator for PhotonMapper {
+    fn radiance(&self, world: &HittableList, ray: &Ray) -> Array1<f64> {
+        self.trace(world, ray, 1, Chain::Specular)
+    }
+
+    /**
+     * Shoots the photons, half from the lights and half from the
+     * background (only from one of them when there are no lights, or
+     * nothing specular to aim at), and builds the photon map.
+     */
+    fn preprocess(&mut self, world: &HittableList) {
+        let targets = PhotonMapper::specular_targets(world);
+        let from_lights = match (world.lights.is_empty(), targets.is_empty()) {
+            (true, true) => {
+                self.map = PhotonMap::default();
+                return;
+            }
+            (true, false) => 0.0,
+            (false, true) => 1.0,
+            (false, false) => 0.5,
+        };
+
+        // Far enough for background photons to start outside the world.
+        let distance = match world.bounding_box(0.0, 0.0) {
+            Some(aabb) => {
+                2.0 * Vec4::l2_norm((aabb.max() - aabb.min()).view()) + 1.0
+            }
+            None => 1e5,
+        };
+
+        let mut stored = Vec::new();
+        let photons = self.photons as f64;
+        for _photon in 0..self.photons {
+            if sampler::next_1d() < from_lights {
+                let (hit, pdf) = world.sample_light().unwrap();
+                let emission = world.materials.get(hit.material).emission(&hit);
+                let direction = cosine_direction(&hit.normal);
+
+                // Le cos / (pdf pdf_dir), with pdf_dir = cos / PI.
+                let power = emission.unwrap()
+                    * (std::f64::consts::PI / (pdf * from_lights * photons));
+                let ray = Ray::new(hit.point, direction);
+                self.trace_photon(world, ray, power, &mut stored);
+            } else {
+                let (ray, sky, pdf) =
+                    PhotonMapper::background_photon(&targets, distance);
+                let power = sky / (pdf * (1.0 - from_lights) * photons);
+                self.trace_photon(world, ray, power, &mut stored);
+            }
+        }
+
+        self.map = PhotonMap::new(stored);
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Metropolis light transport in primary sample space (PSSMLT). A path is
+ * the sequence of random numbers the base integrator draws for a camera
+ * sample (see the sampler module), Markov chains mutate them and visit
+ * paths proportionally to their luminance. Bright paths that are hard to
+ * find (caustics, light through small openings) are explored once found,
+ * instead of being found again for every pixel.
+ *
+ * The image is scaled by b, the average luminance of bootstrap independent
+ * paths, which also pick the starting point of the chains. The Canvas
+ * samples are the mutations per pixel. RGB only, the wavelength of the
+ * spectral mode is not sampled.
+ *
+ * Reference:
+ * * Kelemen et al., "A Simple and Robust Mutation Strategy for the
+ *   Metropolis Light Transport Algorithm", 2002.
+ * * Pharr et al., "Physically Based Rendering", 3rd edition, section 16.4.
+ */
+pub struct Metropolis {
+    pub integrator: Box<dyn Integrator>,
+    pub bootstrap: u32,
+    pub chains: u32,
+    pub large_step_probability: f64,
+    pub sigma: f64,
+}
+
+/**
+ * Luminance (Y) of a linear RGB color, 0 for invalid (NaN, infinite)
+ * estimates.
+ */
+fn luminance(color: &Array1<f64>) -> f64 {
+    let y = spectrum::luminance(color);
+    if y.is_finite() {
+        y.max(0.0)
+    } else {
+        0.0
+    }
+}
+
+impl Metropolis {
+    pub fn new(integrator: Box<dyn Integrator>) -> Metropolis {
+        Metropolis {
+            integrator,
+            bootstrap: 10_000,
+            chains: 64,
+            large_step_probability: 0.3,
+            sigma: 0.01,
+        }
+    }
+
+    fn primary_sampler(&self, seed: u64) -> Rc<RefCell<MetropolisSampler>> {
+        Rc::new(RefCell::new(MetropolisSampler::new(
+            seed,
+            self.large_step_probability,
+            self.sigma,
+        )))
+    }
+
+    /**
+     * Radiance of the path of the primary sampler, whose first two numbers
+     * are the position on the image, and the index of the pixel it lands.
+     */
+    fn evaluate(
+        &self,
+        world: &HittableList,
+        camera: &Camera,
+        primary: &Rc<RefCell<MetropolisSampler>>,
+    ) -> (Array1<f64>, usize) {
+        let (width, height) = (camera.resolution_x, camera.resolution_y);
+        sampler::with_sampler(Box::new(Rc::clone(primary)), || {
+            let x = sampler::gen_range(0.0, width as f64);
+            let y = sampler::gen_range(0.0, height as f64);
+            let ray = camera.get_ray(x, y);
+            let pixel = (y as usize).min(height as usize - 1) * width as usize
+                + (x as usize).min(width as usize - 1);
+
+            (self.integrator.radiance(world, &ray), pixel)
+        })
+    }
+
+    /**
+     * Runs a chain for the mutations, from a bootstrap path chosen
+     * proportionally to its weight, and accumulates its RGB contributions
+     * per pixel. Both the proposed and the current paths are splatted,
+     * weighted by their acceptance probabilities (expected values).
+     */
+    fn run_chain(
+        &self,
+        world: &HittableList,
+        camera: &Camera,
+        weights: &[f64],
+        seed: u64,
+        chain: u64,
+        mutations: u64,
+    ) -> Vec<f64> {
+        // Streams after the ones of the bootstrap paths.
+        let mut rng = RandomSampler::seeded(seed, weights.len() as u64 + chain);
+        let mut remaining = rng.next() * weights.iter().sum::<f64>();
+        let index = weights
+            .iter()
+            .position(|weight| {
+                remaining -= weight;
+                remaining < 0.0 && *weight > 0.0
+            })
+            .unwrap_or_else(|| {
+                weights.iter().rposition(|weight| *weight > 0.0).unwrap()
+            });
+
+        let primary = self.primary_sampler(sampler::mix(seed, index as u64));
+        let (mut current, mut pixel) = self.evaluate(world, camera, &primary);
+        let pixels = (camera.resolution_x * camera.resolution_y) as usize;
+        let mut image = vec![0.0; pixels * 3];
+        let mut splat = |pixel: usize, color: &Array1<f64>, weight: f64| {
+            for channel in 0..3 {
+                image[pixel * 3 + channel] += color[channel] * weight;
+            }
+        };
+
+        for _mutation in 0..mutations {
+            primary.borrow_mut().start_iteration();
+            let (proposed, proposed_pixel) =
+                self.evaluate(world, camera, &primary);
+
+            let current_y = luminance(&current);
+            let proposed_y = luminance(&proposed);
+            let accept = if current_y > 0.0 {
+                (proposed_y / current_y).min(1.0)
+            } else {
+                1.0
+            };
+            if proposed_y > 0.0 {
+                splat(proposed_pixel, &proposed, accept / proposed_y);
+            }
+            if current_y > 0.0 {
+                splat(pixel, &current, (1.0 - accept) / current_y);
+            }
+
+            if rng.next() < accept {
+                current = proposed;
+                pixel = proposed_pixel;
+                primary.borrow_mut().accept();
+            } else {
+                primary.borrow_mut().reject();
+            }
+        }
+
+        image
+    }
+}
+
+impl Default for Metropolis {
+    fn default() -> Self {
+        Self::new(Box::new(PathTracer::new()))
+    }
+}
+
+impl Integrator for Metropolis {
+    fn radiance(&self, world: &HittableList, ray: &Ray) -> Array1<f64> {
+        self.integrator.radiance(world, ray)
+    }
+
+    fn preprocess(&mut self, world: &HittableList) {
+        self.integrator.preprocess(world);
+    }
+
+    fn render(
+        &self,
+        world: &HittableList,
+        camera: &Camera,
+        samples: u32,
+    ) -> Option<Vec<Array1<f64>>> {
+        let pixels = (camera.resolution_x * camera.resolution_y) as usize;
+
+        // Independent paths (seeds of their primary sampler), the chains
+        // start from them proportionally to their luminance. Every thread
+        // has its own sampler, the seeds are derived from the caller's.
+        let seed = sampler::next_seed();
+        let weights: Vec<f64> = (0..self.bootstrap as u64)
+            .into_par_iter()
+            .map(|index| {
+                let primary = self.primary_sampler(sampler::mix(seed, index));
+                luminance(&self.evaluate(world, camera, &primary).0)
+            })
+            .collect();
+        let b = weights.iter().sum::<f64>() / self.bootstrap as f64;
+        if b <= 0.0 {
+            return Some(vec![arr1(&[0.0, 0.0, 0.0, 1.0]); pixels]);
+        }
+
+        let chains = self.chains.max(1) as u64;
+        let mutations = samples as u64 * pixels as u64;
+        let image = (0..chains)
+            .into_par_iter()
+            .map(|chain| {
+                let count = mutations / chains
+                    + if chain < mutations % chains { 1 } else { 0 };
+                self.run_chain(world, camera, &weights, seed, chain, count)
+            })
+            .reduce(
+                || vec![0.0; pixels * 3],
+                |mut image, chain| {
+                    for (value, other) in image.iter_mut().zip(chain) {
+                        *value += other;
+                    }
+                    image
+                },
+            );
+
+        let scale = b / samples as f64;
+        Some(
+            image
+                .chunks(3)
+                .map(|rgb| {
+                    arr1(&[rgb[0] * scale, rgb[1] * scale, rgb[2] * scale, 1.0])
+                })
+                .collect(),
+        )
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Tests
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::actor::RayTraceable;
+    use crate::raytracer::actor::Sphere;
+    use crate::raytracer::material::Dielectric;
+    use crate::raytracer::material::Lambertian;
+    use crate::raytracer::material::MaterialLibrary;
+    use crate::raytracer::material::Primary;
+    use crate::raytracer::material::Shading;
+    use crate::raytracer::scenes::cornell_box;
+
+    fn world() -> HittableList {
+        let mut materials = MaterialLibrary::new();
+        let light = materials.add(
+            "light",
+            Box::new(Primary::new(arr1(&[4.0, 4.0, 4.0, 1.0]), Shading::COLOR)),
+        );
+        let diffuse = materials.add(
+            "diffuse",
+            Box::new(Lambertian::new(
+                arr1(&[0.5, 0.5, 0.5, 1.0]),
+                Shading::COLOR,
+            )),
+        );
+
+        HittableList::new(
+            vec![
+                Box::new(Sphere::new(arr1(&[0.0, 0.0, -2.0, 1.0]), 0.5, light))
+                    as Box<dyn RayTraceable>,
+                Box::new(Sphere::new(arr1(&[0.0, 0.0, 2.0, 1.0]), 0.5, diffuse))
+                    as Box<dyn RayTraceable>,
+            ],
+            materials,
+        )
+    }
+
+    #[test]
+    fn integrator_emitter_and_background() {
+        let world = world();
+        let to_light =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, -1.0, 0.0]));
+        let to_sky =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 1.0, 0.0, 0.0]));
+
+        let integrators: Vec<Box<dyn Integrator>> =
+            vec![Box::new(PathTracer::new()), Box::new(DirectLighting::new())];
+        for integrator in integrators.iter() {
+            assert!(
+                integrator.radiance(&world, &to_light)
+                    == arr1(&[4.0, 4.0, 4.0, 1.0])
+            );
+            assert!(
+                integrator.radiance(&world, &to_sky)
+                    == background_color(&to_sky)
+            );
+        }
+
+        // Camera rays only, the diffuse sphere is not lit.
+        let camera_only = PathTracer {
+            max_depth: 1,
+            ..PathTracer::new()
+        };
+        let to_diffuse =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, 1.0, 0.0]));
+        assert!(camera_only.radiance(&world, &to_diffuse)[0] == 0.0);
+    }
+
+    #[test]
+    fn integrator_direct_lighting() {
+        // Same estimate as the camera ray and a single bounce of the path
+        // tracer, but sampling the (small) light makes it far less noisy.
+        let world = world();
+        let to_diffuse =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, 1.0, 0.0]));
+        let one_bounce = PathTracer {
+            max_depth: 2,
+            ..PathTracer::new()
+        };
+        let samples = 20000;
+        let statistics = |integrator: &dyn Integrator| {
+            let values: Vec<f64> = (0..samples)
+                .map(|_| integrator.radiance(&world, &to_diffuse)[0])
+                .collect();
+            let mean = values.iter().sum::<f64>() / samples as f64;
+            let variance = values
+                .iter()
+                .map(|value| (value - mean) * (value - mean))
+                .sum::<f64>()
+                / samples as f64;
+            (mean, variance)
+        };
+
+        let seeded = RandomSampler::seeded(0, 0);
+        sampler::with_sampler(Box::new(seeded), || {
+            let (path, path_variance) = statistics(&one_bounce);
+            let (direct, direct_variance) = statistics(&DirectLighting::new());
+            assert!((direct - path).abs() < 0.03 * path);
+            assert!(direct_variance < 0.5 * path_variance);
+        });
+    }
+
+    #[test]
+    fn integrator_ambient_occlusion() {
+        let world = world();
+        let to_diffuse =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, 1.0, 0.0]));
+
+        // Nothing within reach, but the other sphere occludes part of the
+        // hemisphere (facing the origin) within a large distance.
+        let seeded = RandomSampler::seeded(0, 0);
+        sampler::with_sampler(Box::new(seeded), || {
+            let near = AmbientOcclusion::new(0.1);
+            assert!(near.radiance(&world, &to_diffuse)[0] == 1.0);
+
+            let far = AmbientOcclusion::new(100.0);
+            let samples = 2000;
+            let visible = (0..samples)
+                .map(|_| far.radiance(&world, &to_diffuse)[0])
+                .sum::<f64>()
+                / samples as f64;
+            assert!(visible < 1.0 && visible > 0.5);
+        });
+    }
+
+    #[test]
+    fn integrator_debug_outputs() {
+        let world = world();
+        let to_light =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, -1.0, 0.0]));
+        let to_sky =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 1.0, 0.0, 0.0]));
+        let radiance = |output: DebugOutput, ray: &Ray| {
+            DebugView::new(output).radiance(&world, ray)
+        };
+
+        assert!(
+            radiance(DebugOutput::Normals, &to_light)
+                == arr1(&[0.5, 0.5, 1.0, 1.0])
+        );
+        assert!(
+            radiance(DebugOutput::Normals, &to_sky)
+                == arr1(&[0.0, 0.0, 0.0, 1.0])
+        );
+
+        let depth =
+            radiance(DebugOutput::Depth { max_distance: 3.0 }, &to_light);
+        assert!((depth[0] - 0.5).abs() < 1e-9);
+
+        let uv = radiance(DebugOutput::Uv, &to_light);
+        assert!((uv[0] - 0.25).abs() < 1e-9 && (uv[1] - 0.5).abs() < 1e-9);
+
+        // Different materials get different colors.
+        let to_diffuse =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, 1.0, 0.0]));
+        assert!(
+            radiance(DebugOutput::MaterialId, &to_light)
+                != radiance(DebugOutput::MaterialId, &to_diffuse)
+        );
+
+        // Every actor is tested once by the list, which is all of them.
+        let cost =
+            radiance(DebugOutput::TraversalCost { max_cost: 2.0 }, &to_sky);
+        assert!(cost == arr1(&[1.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn integrator_bidirectional_cornell() {
+        // Both converge to the same image. The (noisy) path tracer needs
+        // many samples, so the average radiance over each quarter of the
+        // image is compared instead of pixels (with the same camera rays).
+        let mut materials = MaterialLibrary::new();
+        let actors = cornell_box(&mut materials);
+        let world = HittableList::new(actors, materials);
+        let size = 8;
+        let camera = Camera::new(
+            70.0,
+            size,
+            size,
+            arr1(&[0.0, 0.0, 0.95, 1.0]),
+            arr1(&[0.0, 0.0, 0.0, 1.0]),
+            arr1(&[0.0, 1.0, 0.0, 0.0]),
+            0.0,
+        );
+        let path_tracer = PathTracer {
+            max_depth: 10,
+            roulette_depth: 10,
+        };
+        let bidirectional = BidirectionalPathTracer::new();
+
+        let half = (size / 2) as f64;
+        let samples = 16000;
+        // Seeded, so that the comparison does not depend on the run.
+        let seeded = RandomSampler::seeded(0, 0);
+        sampler::with_sampler(Box::new(seeded), || {
+            for quarter in 0..4 {
+                let x0 = (quarter % 2) as f64 * half;
+                let y0 = (quarter / 2) as f64 * half;
+                let mut path = 0.0;
+                let mut bdpt = 0.0;
+                for _ in 0..samples {
+                    let ray = camera.get_ray(
+                        x0 + sampler::gen_range(0.0, half),
+                        y0 + sampler::gen_range(0.0, half),
+                    );
+                    path += path_tracer
+                        .radiance(&world, &ray)
+                        .slice(s![0..3])
+                        .sum();
+                    bdpt += bidirectional
+                        .radiance(&world, &ray)
+                        .slice(s![0..3])
+                        .sum();
+                }
+
+                assert!(
+                    (bdpt - path).abs() < 0.1 * path,
+                    "quarter {}: {} (bidirectional) vs {} (path tracer)",
+                    quarter,
+                    bdpt / samples as f64,
+                    path / samples as f64
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn integrator_metropolis() {
+        // Same image as the path tracer (looking off-center at the light,
+        // so that the quarters differ), compared per quarter as above.
+        let world = world();
+        let size = 8;
+        let camera = Camera::new(
+            70.0,
+            size,
+            size,
+            arr1(&[0.0, 0.0, 0.0, 1.0]),
+            arr1(&[0.4, 0.3, -1.0, 1.0]),
+            arr1(&[0.0, 1.0, 0.0, 0.0]),
+            0.0,
+        );
+        let path_tracer = PathTracer::new();
+        let metropolis = Metropolis::default();
+
+        // Seeded, so that the comparison does not depend on the run (the
+        // chains draw their seeds from it).
+        let seeded = RandomSampler::seeded(0, 0);
+        sampler::with_sampler(Box::new(seeded), || {
+            let image = metropolis.render(&world, &camera, 2000).unwrap();
+
+            let half = size / 2;
+            let samples = 16000;
+            for quarter in 0..4 {
+                let x0 = (quarter % 2) * half;
+                let y0 = (quarter / 2) * half;
+                let mut path = 0.0;
+                for _ in 0..samples {
+                    let ray = camera.get_ray(
+                        x0 as f64 + sampler::gen_range(0.0, half as f64),
+                        y0 as f64 + sampler::gen_range(0.0, half as f64),
+                    );
+                    path += path_tracer
+                        .radiance(&world, &ray)
+                        .slice(s![0..3])
+                        .sum();
+                }
+                path /= samples as f64;
+
+                let mut mlt = 0.0;
+                for y in y0..y0 + half {
+                    for x in x0..x0 + half {
+                        mlt += image[(y * size + x) as usize]
+                            .slice(s![0..3])
+                            .sum();
+                    }
+                }
+                mlt /= (half * half) as f64;
+
+                assert!(
+                    (mlt - path).abs() < 0.1 * path,
+                    "quarter {}: {} (metropolis) vs {} (path tracer)",
+                    quarter,
+                    mlt,
+                    path
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn integrator_photon_caustics() {
+        // Glass sphere on a diffuse ground, lit by the background: the
+        // light it focuses lands below it.
+        let mut materials = MaterialLibrary::new();
+        let glass = materials.add(
+            "glass",
+            Box::new(Dielectric::new(
+                arr1(&[1.0, 1.0, 1.0, 0.0]),
+                Shading::COLOR,
+                1.5,
+            )),
+        );
+        let ground = materials.add(
+            "ground",
+            Box::new(Lambertian::new(
+                arr1(&[0.5, 0.5, 0.5, 1.0]),
+                Shading::COLOR,
+            )),
+        );
+        let world = HittableList::new(
+            vec![
+                Box::new(Sphere::new(
+                    arr1(&[0.0, -1000.0, 0.0, 1.0]),
+                    1000.0,
+                    ground,
+                )) as Box<dyn RayTraceable>,
+                Box::new(Sphere::new(arr1(&[0.0, 1.0, 0.0, 1.0]), 1.0, glass))
+                    as Box<dyn RayTraceable>,
+            ],
+            materials,
+        );
+
+        let mut photon_mapper = PhotonMapper::new();
+        photon_mapper.photons = 20000;
+        let seeded = RandomSampler::seeded(0, 0);
+        sampler::with_sampler(Box::new(seeded), || {
+            photon_mapper.preprocess(&world);
+            assert!(!photon_mapper.photon_map().is_empty());
+
+            let caustics = |x: f64| {
+                let mut hit = Hit::new();
+                hit.point = arr1(&[x, 0.0, 0.0, 1.0]);
+                hit.normal = arr1(&[0.0, 1.0, 0.0, 0.0]);
+                let ray = Ray::new(
+                    arr1(&[x, 1.0, 0.0, 1.0]),
+                    arr1(&[0.0, -1.0, 0.0, 0.0]),
+                );
+                let reflectance = arr1(&[0.5, 0.5, 0.5, 1.0]);
+                photon_mapper.caustics(&hit, &reflectance, &ray)[0]
+            };
+            assert!(caustics(0.3) > 0.0);
+            assert!(caustics(0.3) > 10.0 * caustics(5.0));
+        });
+    }
+}
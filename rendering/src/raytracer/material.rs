@@ -1,26 +1,37 @@
 use crate::raytracer::actor::Hit;
 use crate::raytracer::common::Ray;
 use crate::raytracer::common::Vec4;
+use crate::raytracer::sampler;
+use crate::raytracer::spectrum::RGB_WAVELENGTHS;
+use crate::raytracer::texture::Texture;
 
-use ndarray::{arr1, Array1};
-use rand::Rng;
+use ndarray::{arr1, s, Array1};
+use num::complex::Complex64;
+use std::collections::HashMap;
+use std::sync::Arc;
 
-fn random_dir_unit_sphere() -> Array1<f64> {
-    let mut dir = arr1(&[std::f64::MAX, 0.0, 0.0]);
-    let mut rng = rand::thread_rng();
-    let min = -1.0;
-    let max = 1.0;
+/**
+ * Uniformly distributed point in the unit ball: a direction and a radius
+ * (the cubic root, as the volume grows with its cube).
+ */
+pub fn random_dir_unit_sphere() -> Array1<f64> {
+    let dir = random_unit_vector();
+    dir * sampler::next_1d().cbrt()
+}
 
-    while Vec4::squared_length(dir.view()) >= 1.0 {
-        dir = arr1(&[
-            rng.gen_range(min, max),
-            rng.gen_range(min, max),
-            rng.gen_range(min, max),
-            0.0,
-        ]);
-    }
+/**
+ * Uniformly distributed unit vector: z = 1 - 2 u is uniform in [-1, 1]
+ * (Archimedes' hat-box theorem) and phi = 2 PI v. Added to a (unit)
+ * normal, it gives directions distributed with the cosine around it, which
+ * is how diffuse reflection is sampled (points inside the sphere would not
+ * be).
+ */
+pub fn random_unit_vector() -> Array1<f64> {
+    let z = 1.0 - 2.0 * sampler::next_1d();
+    let phi = 2.0 * std::f64::consts::PI * sampler::next_1d();
+    let r = (1.0 - z * z).max(0.0).sqrt();
 
-    dir
+    arr1(&[r * phi.cos(), r * phi.sin(), z, 0.0])
 }
 
 /**
@@ -51,20 +62,128 @@ pub fn schlick(cosine: f64, ref_idx: f64) -> f64 {
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
 
+/**
+ * Schlick's approximation for a per-channel reflectance at normal incidence
+ * (F0). Used for tinted specular reflections (e.g. metals), where F0 is
+ * the color of the surface itself.
+ */
+pub fn schlick_color(cosine: f64, f0: &Array1<f64>) -> Array1<f64> {
+    f0 + &((1.0 - f0) * (1.0 - cosine).powi(5))
+}
+
+/**
+ * Fresnel reflectance of a conductor (unpolarized light), for a complex
+ * index of refraction eta + i k relative to the outside medium.
+ *
+ * Unlike dielectrics, conductors absorb the transmitted light almost
+ * immediately, which is what makes k (the extinction coefficient) relevant.
+ *
+ * Reference:
+ * * Pharr, Jakob, Humphreys, "Physically Based Rendering", 3rd ed., 8.2.1.
+ */
+pub fn fresnel_conductor(cosine: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cosine * cosine;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cosine * a;
+    let r_s = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+
+    0.5 * (r_p + r_s)
+}
+
+/**
+ * Thin transparent film (soap, oil, oxide layer) coating a surface, with
+ * its thickness in nm.
+ */
+#[derive(Clone)]
+pub struct ThinFilm {
+    pub thickness: f64,
+    pub refraction_idx: f64,
+}
+
+/**
+ * Reflectance of a surface coated by a thin film, at a single wavelength
+ * (in nm). The light reflected at the top and at the bottom of the film
+ * interferes, so the reflectance oscillates with the wavelength, the
+ * thickness and the viewing angle (iridescence).
+ *
+ * Uses the Airy summation of the multiple reflections inside the film for
+ * each polarization. The index of refraction of the base (eta_t) is
+ * complex, so that it handles both dielectrics (k = 0) and conductors.
+ *
+ * Reference:
+ * * Born, Wolf, "Principles of Optics", 7th ed., 1.6.4.
+ */
+pub fn thin_film_reflectance(
+    cosine: f64,
+    wavelength: f64,
+    film: &ThinFilm,
+    eta_i: f64,
+    eta_t: Complex64,
+) -> f64 {
+    let one = Complex64::new(1.0, 0.0);
+    let n1 = Complex64::new(eta_i, 0.0);
+    let n2 = Complex64::new(film.refraction_idx, 0.0);
+    let n3 = eta_t;
+
+    // Snell's law for the angles inside the film and inside the base
+    // (complex when the wave is evanescent or absorbed).
+    let sin2 = Complex64::new(1.0 - cosine * cosine, 0.0);
+    let cos1 = Complex64::new(cosine, 0.0);
+    let cos2 = (one - (n1 / n2) * (n1 / n2) * sin2).sqrt();
+    let cos3 = (one - (n1 / n3) * (n1 / n3) * sin2).sqrt();
+
+    // Fresnel amplitude coefficients, (s)- and (p)-polarization.
+    let r12_s = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+    let r12_p = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+    let r23_s = (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3);
+    let r23_p = (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3);
+
+    // Phase difference between two consecutive reflections.
+    let delta =
+        4.0 * std::f64::consts::PI * n2 * film.thickness * cos2 / wavelength;
+    let phase = (Complex64::new(0.0, 1.0) * delta).exp();
+
+    let airy = |r12: Complex64, r23: Complex64| {
+        ((r12 + r23 * phase) / (one + r12 * r23 * phase)).norm_sqr()
+    };
+
+    0.5 * (airy(r12_s, r23_s) + airy(r12_p, r23_p))
+}
+
+/**
+ * NORMALS shows the normals instead of the color of the material. The
+ * DebugView integrator (DebugOutput::Normals) does the same for any
+ * material, without changing the scene.
+ */
 #[derive(Clone)]
 pub enum Shading {
     COLOR,
     NORMALS,
 }
 
-pub trait Scattering: Sync {
+pub trait Scattering: Sync + Send {
+    /**
+     * Returns whether the incident ray is scattered (absorbed otherwise),
+     * in which case color_noscatter is used instead. How long paths get
+     * (maximum depth, Russian roulette) is decided by the integrator.
+     */
     fn scatter(
         &self,
         incident: &Ray,
         hit_record: &Hit,
         attenuation: &mut Array1<f64>,
         scattered: &mut Ray,
-        depth: u32,
     ) -> bool;
 
     fn color(&self, hit: &Hit) -> Array1<f64>;
@@ -72,6 +191,43 @@ pub trait Scattering: Sync {
     fn clone_box(&self) -> Box<dyn Scattering>;
 
     fn color_noscatter(&self, hit: &Hit) -> Array1<f64>;
+
+    /**
+     * Participating medium filling the interior of the actor, if any. Rays
+     * scattered into the actor then perform a random walk inside it (see
+     * Canvas), until they leave through the surface again.
+     */
+    fn medium(&self) -> Option<Medium> {
+        None
+    }
+
+    /**
+     * Opacity in [0.0, 1.0] at the hit point. Hits on (partially)
+     * transparent surfaces are stochastically skipped by the alpha test
+     * (see HittableList), so rays go through them untouched.
+     */
+    fn opacity(&self, _hit: &Hit) -> f64 {
+        1.0
+    }
+
+    /**
+     * Radiance emitted at the hit point, for materials that are light
+     * sources. Bidirectional integrators start light paths on the actors
+     * made of them (see HittableList).
+     */
+    fn emission(&self, _hit: &Hit) -> Option<Array1<f64>> {
+        None
+    }
+
+    /**
+     * Reflectance of ideal diffuse reflectors, whose BSDF (reflectance /
+     * PI) bidirectional integrators evaluate to connect paths. The other
+     * materials can only be sampled (scatter), and they are treated as
+     * specular there.
+     */
+    fn diffuse(&self, _hit: &Hit) -> Option<Array1<f64>> {
+        None
+    }
 }
 
 //https://users.rust-lang.org/t/solved-is-it-possible-to-clone-a-boxed-trait-object/1714/5
@@ -81,6 +237,80 @@ impl Clone for Box<dyn Scattering> {
     }
 }
 
+// ----------------------------------------------------------------------------
+/**
+ * Handle of a material registered in a MaterialLibrary. Actors and hits
+ * refer to their material through it, instead of owning a copy.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialId(pub usize);
+
+/**
+ * Scene-level registry of (shared) materials, by name and by handle.
+ *
+ * Materials are registered once and can be used by any number of actors.
+ * Registering a material under an existing name replaces it (keeping its
+ * handle), so that a material can be edited and the scene re-rendered.
+ */
+#[derive(Clone, Default)]
+pub struct MaterialLibrary {
+    materials: Vec<Arc<dyn Scattering>>,
+    names: HashMap<String, MaterialId>,
+}
+
+impl MaterialLibrary {
+    pub fn new() -> MaterialLibrary {
+        MaterialLibrary {
+            materials: Vec::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        name: &str,
+        material: Box<dyn Scattering>,
+    ) -> MaterialId {
+        if let Some(id) = self.id(name) {
+            self.replace(id, material);
+            return id;
+        }
+
+        let id = MaterialId(self.materials.len());
+        self.materials.push(Arc::from(material));
+        self.names.insert(String::from(name), id);
+
+        id
+    }
+
+    pub fn replace(&mut self, id: MaterialId, material: Box<dyn Scattering>) {
+        self.materials[id.0] = Arc::from(material);
+    }
+
+    pub fn id(&self, name: &str) -> Option<MaterialId> {
+        self.names.get(name).copied()
+    }
+
+    /**
+     * Panics if the handle does not belong to this library.
+     */
+    pub fn get(&self, id: MaterialId) -> &dyn Scattering {
+        &*self.materials[id.0]
+    }
+
+    pub fn get_shared(&self, id: MaterialId) -> Arc<dyn Scattering> {
+        self.materials[id.0].clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+}
+
 // ----------------------------------------------------------------------------
 /**
  * 0 Scattering material (only primary rays).
@@ -107,10 +337,9 @@ impl Scattering for Primary {
         hit_record: &Hit,
         attenuation: &mut Array1<f64>,
         _scattered: &mut Ray,
-        depth: u32,
     ) -> bool {
         *attenuation = self.color(&hit_record);
-        depth < 1
+        false
     }
 
     fn color(&self, hit: &Hit) -> Array1<f64> {
@@ -131,6 +360,10 @@ impl Scattering for Primary {
         self.color(hit)
     }
 
+    fn emission(&self, hit: &Hit) -> Option<Array1<f64>> {
+        Some(self.color(hit))
+    }
+
     fn clone_box(&self) -> Box<dyn Scattering> {
         Box::new((*self).clone())
     }
@@ -163,11 +396,10 @@ impl Scattering for Lambertian {
         hit_record: &Hit,
         attenuation: &mut Array1<f64>,
         scattered: &mut Ray,
-        depth: u32,
     ) -> bool {
         let target = hit_record.point.clone()
             + hit_record.normal.clone()
-            + random_dir_unit_sphere();
+            + random_unit_vector();
 
         *scattered = Ray::new(
             hit_record.point.clone(),
@@ -176,13 +408,17 @@ impl Scattering for Lambertian {
 
         *attenuation = self.color(&hit_record);
 
-        depth < 50
+        true
     }
 
     fn color_noscatter(&self, _hit: &Hit) -> Array1<f64> {
         arr1(&[0.0, 0.0, 0.0, 0.0])
     }
 
+    fn diffuse(&self, hit: &Hit) -> Option<Array1<f64>> {
+        Some(self.color(hit))
+    }
+
     fn color(&self, hit: &Hit) -> Array1<f64> {
         match self.shading {
             Shading::COLOR => return self.albedo.clone(),
@@ -233,12 +469,11 @@ impl Scattering for Metal {
         hit_record: &Hit,
         attenuation: &mut Array1<f64>,
         scattered: &mut Ray,
-        depth: u32,
     ) -> bool {
         *scattered = reflect(self.fuzz, &incident, &hit_record);
         *attenuation = self.color(&hit_record);
 
-        scattered.direction.dot(&hit_record.normal) > 0.0 && depth < 50
+        scattered.direction.dot(&hit_record.normal) > 0.0
     }
 
     fn color(&self, hit: &Hit) -> Array1<f64> {
@@ -264,6 +499,182 @@ impl Scattering for Metal {
     }
 }
 
+// ----------------------------------------------------------------------------
+/**
+ * Conductor, a metal whose reflectance is driven by its measured complex
+ * index of refraction (eta + i k) per channel through the full conductor
+ * Fresnel equation, instead of a flat color (see Metal).
+ *
+ * Reflections are white at grazing angles and tinted at normal incidence,
+ * as in real metals. An optional thin film (e.g. an oxide layer, as in
+ * heat-tinted titanium or steel) makes them iridescent.
+ */
+
+// Derives self.clone(), which is then used in the clone_box implementation.
+#[derive(Clone)]
+pub struct Conductor {
+    pub eta: Array1<f64>,
+    pub k: Array1<f64>,
+    pub shading: Shading,
+    pub fuzz: f64,
+    pub thin_film: Option<ThinFilm>,
+}
+
+/**
+ * Measured complex indices of refraction (symbol, name, eta, k) sampled at
+ * the (r, g, b) wavelengths.
+ */
+pub const CONDUCTOR_PRESETS: [(&str, &str, [f64; 3], [f64; 3]); 6] = [
+    (
+        "au",
+        "gold",
+        [0.18299, 0.42108, 1.3734],
+        [3.4242, 2.3459, 1.7704],
+    ),
+    (
+        "ag",
+        "silver",
+        [0.15943, 0.14512, 0.13547],
+        [3.9291, 3.19, 2.3808],
+    ),
+    (
+        "cu",
+        "copper",
+        [0.27105, 0.67693, 1.3164],
+        [3.6092, 2.6248, 2.2921],
+    ),
+    (
+        "al",
+        "aluminium",
+        [1.3456, 0.96521, 0.61722],
+        [7.4746, 6.3995, 5.3031],
+    ),
+    (
+        "cr",
+        "chromium",
+        [3.1071, 3.1812, 2.323],
+        [3.3314, 3.3291, 3.135],
+    ),
+    (
+        "fe",
+        "iron",
+        [2.9114, 2.9497, 2.5845],
+        [3.0893, 2.9318, 2.767],
+    ),
+];
+
+impl Conductor {
+    pub fn new(
+        eta: Array1<f64>,
+        k: Array1<f64>,
+        shading: Shading,
+        fuzz: f64,
+    ) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            shading,
+            fuzz,
+            thin_film: None,
+        }
+    }
+
+    /**
+     * Conductor from one of the CONDUCTOR_PRESETS, selected either by its
+     * chemical symbol or by its name (case insensitive, e.g. "Au", "gold").
+     */
+    pub fn from_preset(
+        name: &str,
+        shading: Shading,
+        fuzz: f64,
+    ) -> Result<Conductor, String> {
+        let name = name.to_lowercase();
+        let preset = CONDUCTOR_PRESETS
+            .iter()
+            .find(|preset| preset.0 == name || preset.1 == name);
+
+        match preset {
+            Some((_, _, eta, k)) => Ok(Conductor::new(
+                arr1(&[eta[0], eta[1], eta[2], 0.0]),
+                arr1(&[k[0], k[1], k[2], 0.0]),
+                shading,
+                fuzz,
+            )),
+            None => {
+                let mut message = String::from("Unknown conductor preset: ");
+                message.push_str(name.as_str());
+
+                Err(message)
+            }
+        }
+    }
+
+    /**
+     * Per-channel Fresnel reflectance, with the alpha channel set to 1.0.
+     */
+    pub fn reflectance(&self, cosine: f64) -> Array1<f64> {
+        let channel = |c: usize| match &self.thin_film {
+            Some(film) => thin_film_reflectance(
+                cosine,
+                RGB_WAVELENGTHS[c],
+                film,
+                1.0,
+                Complex64::new(self.eta[c], self.k[c]),
+            ),
+            None => fresnel_conductor(cosine, self.eta[c], self.k[c]),
+        };
+
+        arr1(&[channel(0), channel(1), channel(2), 1.0])
+    }
+}
+
+impl Scattering for Conductor {
+    fn scatter(
+        &self,
+        incident: &Ray,
+        hit_record: &Hit,
+        attenuation: &mut Array1<f64>,
+        scattered: &mut Ray,
+    ) -> bool {
+        let cosine = (-incident.direction.dot(&hit_record.normal)
+            / Vec4::l2_norm(incident.direction.view()))
+        .clamp(0.0, 1.0);
+
+        *scattered = reflect(self.fuzz, incident, hit_record);
+        *attenuation = match self.shading {
+            Shading::COLOR => self.reflectance(cosine),
+            Shading::NORMALS => self.color(hit_record),
+        };
+
+        scattered.direction.dot(&hit_record.normal) > 0.0
+    }
+
+    /**
+     * Reflectance at normal incidence, the color the metal is perceived as.
+     */
+    fn color(&self, hit: &Hit) -> Array1<f64> {
+        match self.shading {
+            Shading::COLOR => self.reflectance(1.0),
+            Shading::NORMALS => {
+                let normal = &hit.normal;
+
+                // In order to use the normal vectors (i,j,k) as (r,g,b)
+                // they need to be mapped from [-1.0, 1.0] to the
+                // [0.0, 1.0] range.
+                (normal + 1.0) * 0.5
+            }
+        }
+    }
+
+    fn color_noscatter(&self, _hit: &Hit) -> Array1<f64> {
+        arr1(&[0.0, 0.0, 0.0, 0.0])
+    }
+
+    fn clone_box(&self) -> Box<dyn Scattering> {
+        Box::new((*self).clone())
+    }
+}
+
 // ----------------------------------------------------------------------------
 /**
  * Dielectric material.
@@ -271,6 +682,18 @@ impl Scattering for Metal {
  * Refracts light following Snell's law and angle-varying reflectivity using
  * a polynomial approximation (Christophe Schlick).
  *
+ * Light traveling inside the medium is attenuated following the
+ * Beer-Lambert law, exp(-absorption * distance), so thick (colored) glass
+ * and liquids get darker than thin ones. The absorption coefficient is per
+ * channel and per unit of distance; it is zero (clear medium) by default.
+ *
+ * In spectral mode, an optional dispersion model makes the index of
+ * refraction depend on the wavelength (e.g. prisms splitting white light).
+ *
+ * An optional thin film on the surface (e.g. soap bubbles, with the
+ * refraction index of the dielectric set to 1.0) makes its reflections
+ * iridescent.
+ *
  */
 
 #[derive(Clone)]
@@ -279,6 +702,46 @@ pub struct Dielectric {
     pub shading: Shading,
     pub refraction_idx: f64,
     pub refraction_idx_ext: f64,
+    pub absorption: Array1<f64>,
+    pub dispersion: Option<Dispersion>,
+    pub thin_film: Option<ThinFilm>,
+}
+
+/**
+ * Index of refraction as a function of the wavelength (lambda, in
+ * micrometers in both equations).
+ *
+ * Cauchy:    n = A + B / lambda^2
+ * Sellmeier: n^2 = 1 + sum_i B_i lambda^2 / (lambda^2 - C_i)
+ */
+#[derive(Clone)]
+pub enum Dispersion {
+    Cauchy { a: f64, b: f64 },
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+/**
+ * Schott N-BK7, the most common optical (crown) glass.
+ */
+pub const BK7: Dispersion = Dispersion::Sellmeier {
+    b: [1.03961212, 0.231792344, 1.01046945],
+    c: [0.00600069867, 0.0200179144, 103.560653],
+};
+
+impl Dispersion {
+    pub fn refraction_idx(&self, wavelength: f64) -> f64 {
+        let lambda2 = (wavelength * 1e-3) * (wavelength * 1e-3);
+
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / lambda2,
+            Dispersion::Sellmeier { b, c } => (1.0
+                + b.iter()
+                    .zip(c.iter())
+                    .map(|(b, c)| b * lambda2 / (lambda2 - c))
+                    .sum::<f64>())
+            .sqrt(),
+        }
+    }
 }
 
 impl Dielectric {
@@ -289,14 +752,79 @@ impl Dielectric {
     ) -> Dielectric {
         // Air
         let refraction_idx_ext = 1.0;
+        let absorption = arr1(&[0.0, 0.0, 0.0, 0.0]);
         Dielectric {
             color,
             shading,
             refraction_idx,
             refraction_idx_ext,
+            absorption,
+            dispersion: None,
+            thin_film: None,
         }
     }
 
+    /**
+     * Per-channel reflectance of the thin film on the surface. In spectral
+     * mode all the channels hold the reflectance at the ray wavelength.
+     */
+    fn film_reflectance(
+        film: &ThinFilm,
+        cosine: f64,
+        wavelength: Option<f64>,
+        eta_i: f64,
+        eta_t: f64,
+    ) -> Array1<f64> {
+        let eta_t = Complex64::new(eta_t, 0.0);
+        let at = |wavelength: f64| {
+            thin_film_reflectance(cosine, wavelength, film, eta_i, eta_t)
+        };
+
+        match wavelength {
+            Some(wavelength) => {
+                let reflectance = at(wavelength);
+                arr1(&[reflectance, reflectance, reflectance, 1.0])
+            }
+            None => arr1(&[
+                at(RGB_WAVELENGTHS[0]),
+                at(RGB_WAVELENGTHS[1]),
+                at(RGB_WAVELENGTHS[2]),
+                1.0,
+            ]),
+        }
+    }
+
+    /**
+     * Index of refraction for a ray of the given wavelength. Falls back to
+     * refraction_idx without a dispersion model or outside spectral mode.
+     */
+    pub fn refraction_idx_at(&self, wavelength: Option<f64>) -> f64 {
+        match (&self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => {
+                dispersion.refraction_idx(wavelength)
+            }
+            _ => self.refraction_idx,
+        }
+    }
+
+    /**
+     * Absorption coefficient that leaves the given (r, g, b) transmittance
+     * after traveling the given distance through the medium. Easier to
+     * author than the coefficient itself (e.g. "the glass is this color
+     * when it is 1 unit thick").
+     */
+    pub fn absorption_from_transmittance(
+        transmittance: &Array1<f64>,
+        distance: f64,
+    ) -> Array1<f64> {
+        arr1(&[
+            -transmittance[0].ln() / distance,
+            -transmittance[1].ln() / distance,
+            -transmittance[2].ln() / distance,
+            0.0,
+        ])
+    }
+
     /**
      * Snell's Law:
      * n_inci * sin(Theta_inci) = n_trans * sin(Theta_trans)
@@ -340,10 +868,10 @@ impl Scattering for Dielectric {
         hit_record: &Hit,
         attenuation: &mut Array1<f64>,
         scattered: &mut Ray,
-        depth: u32,
     ) -> bool {
+        let refraction_idx = self.refraction_idx_at(incident.wavelength);
         let mut outward_normal = hit_record.normal.clone();
-        let mut ni_over_nt = self.refraction_idx_ext / self.refraction_idx;
+        let mut ni_over_nt = self.refraction_idx_ext / refraction_idx;
         let mut cosine = -hit_record.normal.dot(&incident.direction)
             / Vec4::l2_norm(incident.direction.view());
         let reflect_prob: f64;
@@ -353,33 +881,76 @@ impl Scattering for Dielectric {
         // to be inverted).
         if hit_record.normal.dot(&incident.direction) > 0.0 {
             outward_normal = -hit_record.normal.clone();
-            ni_over_nt = self.refraction_idx / self.refraction_idx_ext;
-            cosine = self.refraction_idx
+            ni_over_nt = refraction_idx / self.refraction_idx_ext;
+            cosine = refraction_idx
                 * hit_record.normal.dot(&incident.direction)
                 / Vec4::l2_norm(incident.direction.view());
         }
         *attenuation = self.color(&hit_record);
 
+        // Exiting the medium, hit_record.t is the distance traveled inside.
+        if let Shading::COLOR = self.shading {
+            if hit_record.normal.dot(&incident.direction) > 0.0 {
+                let distance =
+                    hit_record.t * Vec4::l2_norm(incident.direction.view());
+                *attenuation = attenuation.clone()
+                    * (-distance * &self.absorption).mapv(f64::exp);
+            }
+        }
+
         let reflected = reflect(0.0, &incident, hit_record);
-        if self.refract(
+        let refracted = self.refract(
             &incident,
             outward_normal,
             hit_record,
             ni_over_nt,
             scattered,
-        ) {
-            reflect_prob = schlick(cosine, self.refraction_idx);
+        );
+        if refracted {
+            reflect_prob = schlick(cosine, refraction_idx);
         } else {
             reflect_prob = 1.0;
         }
 
-        let mut rng = rand::thread_rng();
-        rng.fill(&mut [1; 1]);
-        if rng.gen_range(0.0, 1.0) < reflect_prob {
-            *scattered = reflected;
+        match &self.thin_film {
+            Some(film) if refracted => {
+                let exiting = hit_record.normal.dot(&incident.direction) > 0.0;
+                let (eta_i, eta_t) = if exiting {
+                    (refraction_idx, self.refraction_idx_ext)
+                } else {
+                    (self.refraction_idx_ext, refraction_idx)
+                };
+                let cosine = (hit_record.normal.dot(&incident.direction)
+                    / Vec4::l2_norm(incident.direction.view()))
+                .abs();
+                let reflectance = Dielectric::film_reflectance(
+                    film,
+                    cosine,
+                    incident.wavelength,
+                    eta_i,
+                    eta_t,
+                );
+
+                // The reflectance differs per channel, so reflect with the
+                // average probability and re-weight each channel.
+                let prob = reflectance.slice(s![0..3]).sum() / 3.0;
+                let mut weight = if sampler::next_1d() < prob {
+                    *scattered = reflected;
+                    reflectance / prob
+                } else {
+                    (1.0 - reflectance) / (1.0 - prob)
+                };
+                weight[3] = 1.0;
+                *attenuation = attenuation.clone() * weight;
+            }
+            _ => {
+                if sampler::next_1d() < reflect_prob {
+                    *scattered = reflected;
+                }
+            }
         }
 
-        depth < 50
+        true
     }
 
     fn color(&self, hit: &Hit) -> Array1<f64> {
@@ -404,3 +975,772 @@ impl Scattering for Dielectric {
         Box::new((*self).clone())
     }
 }
+
+// ----------------------------------------------------------------------------
+/**
+ * Principled material (Disney BSDF).
+ *
+ * One material model described with the parameters artists (and glTF / OBJ
+ * exporters) think in: base color, metallic, roughness, specular, sheen,
+ * clearcoat and transmission. All of the scalar parameters are in the
+ * [0.0, 1.0] range.
+ *
+ * Each scatter event picks a single lobe (clearcoat, metallic specular,
+ * transmission, dielectric specular or diffuse) with a probability equal to
+ * its weight, so the attenuation of the chosen lobe does not need to be
+ * rescaled.
+ *
+ * Reference:
+ * * Burley, "Physically Based Shading at Disney", SIGGRAPH 2012.
+ */
+
+// Derives self.clone(), which is then used in the clone_box implementation.
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Array1<f64>,
+    pub shading: Shading,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub sheen: f64,
+    pub clearcoat: f64,
+    pub transmission: f64,
+    pub refraction_idx: f64,
+}
+
+impl Principled {
+    /**
+     * Rough, non-metallic and opaque defaults. The remaining parameters are
+     * meant to be overridden through the struct update syntax, e.g.
+     * Principled { metallic: 1.0, ..Principled::new(color, shading) }.
+     */
+    pub fn new(base_color: Array1<f64>, shading: Shading) -> Principled {
+        Principled {
+            base_color,
+            shading,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            sheen: 0.0,
+            clearcoat: 0.0,
+            transmission: 0.0,
+            refraction_idx: 1.5,
+        }
+    }
+}
+
+impl Scattering for Principled {
+    fn scatter(
+        &self,
+        incident: &Ray,
+        hit_record: &Hit,
+        attenuation: &mut Array1<f64>,
+        scattered: &mut Ray,
+    ) -> bool {
+        let cosine = (-incident.direction.dot(&hit_record.normal)
+            / Vec4::l2_norm(incident.direction.view()))
+        .clamp(0.0, 1.0);
+        let white = arr1(&[1.0, 1.0, 1.0, 1.0]);
+
+        // Clearcoat, a colorless and smooth varnish (fixed IOR of 1.5) on
+        // top of every other lobe.
+        let weight = if sampler::next_1d()
+            < self.clearcoat * schlick(cosine, 1.5)
+        {
+            *scattered = reflect(0.0, incident, hit_record);
+            white
+        } else if sampler::next_1d() < self.metallic {
+            // Conductors have no diffuse lobe and tint their reflections.
+            *scattered = reflect(self.roughness, incident, hit_record);
+            schlick_color(cosine, &self.base_color)
+        } else if sampler::next_1d() < self.transmission {
+            return Dielectric::new(
+                self.base_color.clone(),
+                self.shading.clone(),
+                self.refraction_idx,
+            )
+            .scatter(incident, hit_record, attenuation, scattered);
+        } else {
+            // Dielectric base, the specular parameter maps [0.0, 1.0] to a
+            // reflectance at normal incidence (F0) of [0.0, 0.08].
+            let f0 = 0.08 * self.specular;
+            let reflect_prob = f0 + (1.0 - f0) * (1.0 - cosine).powi(5);
+
+            if sampler::next_1d() < reflect_prob {
+                *scattered = reflect(self.roughness, incident, hit_record);
+                white
+            } else {
+                let target = hit_record.point.clone()
+                    + hit_record.normal.clone()
+                    + random_unit_vector();
+                *scattered = Ray::new(
+                    hit_record.point.clone(),
+                    target - hit_record.point.clone(),
+                );
+
+                // Sheen blends the base color towards a white
+                // retro-reflective rim at grazing angles (cloth-like),
+                // without reflecting more than the surface receives.
+                let sheen = self.sheen * (1.0 - cosine).powi(5);
+                let rim = arr1(&[sheen, sheen, sheen, 0.0]);
+                &self.base_color + &((&white - &self.base_color) * &rim)
+            }
+        };
+
+        *attenuation = match self.shading {
+            Shading::COLOR => weight,
+            Shading::NORMALS => self.color(hit_record),
+        };
+
+        scattered.direction.dot(&hit_record.normal) > 0.0
+    }
+
+    fn color(&self, hit: &Hit) -> Array1<f64> {
+        match self.shading {
+            Shading::COLOR => self.base_color.clone(),
+            Shading::NORMALS => {
+                let normal = &hit.normal;
+
+                // In order to use the normal vectors (i,j,k) as (r,g,b)
+                // they need to be mapped from [-1.0, 1.0] to the
+                // [0.0, 1.0] range.
+                (normal + 1.0) * 0.5
+            }
+        }
+    }
+
+    fn color_noscatter(&self, _hit: &Hit) -> Array1<f64> {
+        arr1(&[0.0, 0.0, 0.0, 0.0])
+    }
+
+    fn clone_box(&self) -> Box<dyn Scattering> {
+        Box::new((*self).clone())
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Layered material, a smooth dielectric coat (e.g. varnish or the
+ * clearcoat of car paint) on top of any other material (the base).
+ *
+ * Light is either reflected by the coat, following its Fresnel reflectance
+ * (optionally with thin-film interference), or goes through it and is
+ * scattered by the base. Refraction through the (thin) coat is neglected.
+ */
+
+// Derives self.clone(), which is then used in the clone_box implementation.
+#[derive(Clone)]
+pub struct Layered {
+    pub base: Box<dyn Scattering>,
+    pub refraction_idx: f64,
+    pub thin_film: Option<ThinFilm>,
+}
+
+impl Layered {
+    pub fn new(base: Box<dyn Scattering>, refraction_idx: f64) -> Layered {
+        Layered {
+            base,
+            refraction_idx,
+            thin_film: None,
+        }
+    }
+}
+
+impl Scattering for Layered {
+    fn scatter(
+        &self,
+        incident: &Ray,
+        hit_record: &Hit,
+        attenuation: &mut Array1<f64>,
+        scattered: &mut Ray,
+    ) -> bool {
+        let cosine = -incident.direction.dot(&hit_record.normal)
+            / Vec4::l2_norm(incident.direction.view());
+
+        // Rays leaving the base from the inside (e.g. refractive bases) do
+        // not meet the coat from its outer side.
+        if cosine <= 0.0 {
+            return self.base.scatter(
+                incident,
+                hit_record,
+                attenuation,
+                scattered,
+            );
+        }
+
+        let reflectance = match &self.thin_film {
+            Some(film) => Dielectric::film_reflectance(
+                film,
+                cosine,
+                incident.wavelength,
+                1.0,
+                self.refraction_idx,
+            ),
+            None => {
+                let reflectance = schlick(cosine, self.refraction_idx);
+                arr1(&[reflectance, reflectance, reflectance, 1.0])
+            }
+        };
+        let prob = reflectance.slice(s![0..3]).sum() / 3.0;
+
+        if sampler::next_1d() < prob {
+            *scattered = reflect(0.0, incident, hit_record);
+            *attenuation = reflectance / prob;
+            attenuation[3] = 1.0;
+
+            return scattered.direction.dot(&hit_record.normal) > 0.0;
+        }
+
+        let is_scattered =
+            self.base
+                .scatter(incident, hit_record, attenuation, scattered);
+        let mut weight = (1.0 - reflectance) / (1.0 - prob);
+        weight[3] = 1.0;
+        *attenuation = attenuation.clone() * weight;
+
+        is_scattered
+    }
+
+    fn color(&self, hit: &Hit) -> Array1<f64> {
+        self.base.color(hit)
+    }
+
+    fn color_noscatter(&self, hit: &Hit) -> Array1<f64> {
+        self.base.color_noscatter(hit)
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.base.medium()
+    }
+
+    fn opacity(&self, hit: &Hit) -> f64 {
+        self.base.opacity(hit)
+    }
+
+    fn clone_box(&self) -> Box<dyn Scattering> {
+        Box::new((*self).clone())
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Homogeneous participating medium, described per channel by its
+ * extinction coefficient (the inverse of the mean free path) and its
+ * single-scattering albedo (the fraction of the extinction that scatters
+ * instead of being absorbed). Scattering inside is isotropic.
+ */
+#[derive(Clone)]
+pub struct Medium {
+    pub extinction: Array1<f64>,
+    pub albedo: Array1<f64>,
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Subsurface scattering (e.g. skin, wax, marble). Light refracts through a
+ * smooth dielectric boundary into the actor, and then performs a random
+ * walk inside it (see Medium) until it leaves again, possibly far away
+ * from where it entered. Actors must be closed.
+ *
+ * The mean free path is the average distance traveled between two
+ * scattering events, per channel and in scene units: long paths let the
+ * light go deeper, which gives the characteristic translucency (e.g. red
+ * light in skin).
+ */
+
+// Derives self.clone(), which is then used in the clone_box implementation.
+#[derive(Clone)]
+pub struct Subsurface {
+    pub albedo: Array1<f64>,
+    pub mean_free_path: Array1<f64>,
+    pub refraction_idx: f64,
+    pub shading: Shading,
+}
+
+impl Subsurface {
+    pub fn new(
+        albedo: Array1<f64>,
+        mean_free_path: Array1<f64>,
+        refraction_idx: f64,
+        shading: Shading,
+    ) -> Subsurface {
+        Subsurface {
+            albedo,
+            mean_free_path,
+            refraction_idx,
+            shading,
+        }
+    }
+}
+
+impl Scattering for Subsurface {
+    fn scatter(
+        &self,
+        incident: &Ray,
+        hit_record: &Hit,
+        attenuation: &mut Array1<f64>,
+        scattered: &mut Ray,
+    ) -> bool {
+        // The boundary is a clear dielectric, the color comes from the walk.
+        let boundary = Dielectric::new(
+            arr1(&[1.0, 1.0, 1.0, 1.0]),
+            self.shading.clone(),
+            self.refraction_idx,
+        );
+
+        boundary.scatter(incident, hit_record, attenuation, scattered)
+    }
+
+    fn color(&self, hit: &Hit) -> Array1<f64> {
+        match self.shading {
+            Shading::COLOR => self.albedo.clone(),
+            Shading::NORMALS => (&hit.normal + 1.0) * 0.5,
+        }
+    }
+
+    fn color_noscatter(&self, _hit: &Hit) -> Array1<f64> {
+        arr1(&[0.0, 0.0, 0.0, 0.0])
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        let mut extinction = self.mean_free_path.mapv(|x| 1.0 / x);
+        extinction[3] = 0.0;
+
+        Some(Medium {
+            extinction,
+            albedo: self.albedo.clone(),
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn Scattering> {
+        Box::new((*self).clone())
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Adds surface detail to any other material (the base) by perturbing the
+ * shading normal before the base scatters, without changing the geometry.
+ *
+ * * NormalMap: tangent-space normals encoded as colors, (r, g, b) in
+ *   [0.0, 1.0] mapped to the (tangent, bitangent, normal) components in
+ *   [-1.0, 1.0]. The flat normal is (0.5, 0.5, 1.0).
+ * * BumpMap: scalar height texture (red channel), the normal is tilted
+ *   against the height gradient, computed with finite differences in (u, v)
+ *   and scaled by the given factor.
+ */
+#[derive(Clone)]
+pub enum NormalDetail {
+    NormalMap(Box<dyn Texture>),
+    BumpMap {
+        height: Box<dyn Texture>,
+        scale: f64,
+    },
+}
+
+// Derives self.clone(), which is then used in the clone_box implementation.
+#[derive(Clone)]
+pub struct NormalMapped {
+    pub base: Box<dyn Scattering>,
+    pub detail: NormalDetail,
+}
+
+impl NormalMapped {
+    pub fn new(
+        base: Box<dyn Scattering>,
+        detail: NormalDetail,
+    ) -> NormalMapped {
+        NormalMapped { base, detail }
+    }
+
+    pub fn perturb_normal(&self, hit: &Hit) -> Array1<f64> {
+        let (u, v) = (hit.uv[0], hit.uv[1]);
+        let normal = match &self.detail {
+            NormalDetail::NormalMap(texture) => {
+                let mapped = texture.value(u, v, &hit.point) * 2.0 - 1.0;
+
+                mapped[0] * &hit.tangent
+                    + mapped[1] * &hit.bitangent
+                    + mapped[2] * &hit.normal
+            }
+            NormalDetail::BumpMap { height, scale } => {
+                let delta = 1e-3;
+                let du = (height.value(u + delta, v, &hit.point)[0]
+                    - height.value(u - delta, v, &hit.point)[0])
+                    / (2.0 * delta);
+                let dv = (height.value(u, v + delta, &hit.point)[0]
+                    - height.value(u, v - delta, &hit.point)[0])
+                    / (2.0 * delta);
+
+                &hit.normal
+                    - &(*scale * (du * &hit.tangent + dv * &hit.bitangent))
+            }
+        };
+
+        Vec4::normalize(normal)
+    }
+
+    fn perturbed(&self, hit: &Hit) -> Hit {
+        let mut perturbed = Hit::copy(hit);
+        perturbed.normal = self.perturb_normal(hit);
+
+        perturbed
+    }
+}
+
+impl Scattering for NormalMapped {
+    fn scatter(
+        &self,
+        incident: &Ray,
+        hit_record: &Hit,
+        attenuation: &mut Array1<f64>,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.base.scatter(
+            incident,
+            &self.perturbed(hit_record),
+            attenuation,
+            scattered,
+        )
+    }
+
+    fn color(&self, hit: &Hit) -> Array1<f64> {
+        self.base.color(&self.perturbed(hit))
+    }
+
+    fn color_noscatter(&self, hit: &Hit) -> Array1<f64> {
+        self.base.color_noscatter(&self.perturbed(hit))
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.base.medium()
+    }
+
+    fn opacity(&self, hit: &Hit) -> f64 {
+        self.base.opacity(hit)
+    }
+
+    fn clone_box(&self) -> Box<dyn Scattering> {
+        Box::new((*self).clone())
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Cuts holes into any other material (the base) following the alpha
+ * channel of an opacity texture (e.g. the alpha of a foliage or decal
+ * image), everything else is left to the base.
+ */
+
+// Derives self.clone(), which is then used in the clone_box implementation.
+#[derive(Clone)]
+pub struct Cutout {
+    pub base: Box<dyn Scattering>,
+    pub opacity: Box<dyn Texture>,
+}
+
+impl Cutout {
+    pub fn new(base: Box<dyn Scattering>, opacity: Box<dyn Texture>) -> Cutout {
+        Cutout { base, opacity }
+    }
+}
+
+impl Scattering for Cutout {
+    fn scatter(
+        &self,
+        incident: &Ray,
+        hit_record: &Hit,
+        attenuation: &mut Array1<f64>,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.base
+            .scatter(incident, hit_record, attenuation, scattered)
+    }
+
+    fn color(&self, hit: &Hit) -> Array1<f64> {
+        self.base.color(hit)
+    }
+
+    fn color_noscatter(&self, hit: &Hit) -> Array1<f64> {
+        self.base.color_noscatter(hit)
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.base.medium()
+    }
+
+    fn opacity(&self, hit: &Hit) -> f64 {
+        self.opacity.value(hit.uv[0], hit.uv[1], &hit.point)[3]
+            * self.base.opacity(hit)
+    }
+
+    fn emission(&self, hit: &Hit) -> Option<Array1<f64>> {
+        self.base.emission(hit)
+    }
+
+    fn diffuse(&self, hit: &Hit) -> Option<Array1<f64>> {
+        self.base.diffuse(hit)
+    }
+
+    fn clone_box(&self) -> Box<dyn Scattering> {
+        Box::new((*self).clone())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Tests
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::sampler::RandomSampler;
+
+    #[test]
+    fn fresnel_conductor_limits() {
+        // Normal incidence: ((n - 1)^2 + k^2) / ((n + 1)^2 + k^2)
+        let (eta, k) = (0.27105, 3.6092);
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k)
+            / ((eta + 1.0) * (eta + 1.0) + k * k);
+        assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-9);
+
+        // Every conductor becomes a perfect mirror at grazing angles.
+        assert!((fresnel_conductor(0.0, eta, k) - 1.0).abs() < 1e-9);
+
+        // Without absorption it matches the dielectric Fresnel equation.
+        let r0 = (1.5 - 1.0) * (1.5 - 1.0) / ((1.5 + 1.0) * (1.5 + 1.0));
+        assert!((fresnel_conductor(1.0, 1.5, 0.0) - r0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn conductor_presets() {
+        let gold = Conductor::from_preset("Au", Shading::COLOR, 0.0).unwrap();
+        let by_name =
+            Conductor::from_preset("Gold", Shading::COLOR, 0.0).unwrap();
+        assert!(gold.eta == by_name.eta && gold.k == by_name.k);
+
+        // Gold reflects more red than blue.
+        let color = gold.reflectance(1.0);
+        assert!(color[0] > color[2]);
+        assert_eq!(color[3], 1.0);
+
+        // Silver is almost neutral and very bright.
+        let silver = Conductor::from_preset("ag", Shading::COLOR, 0.0).unwrap();
+        let color = silver.reflectance(1.0);
+        assert!(color[0] > 0.9 && color[2] > 0.85);
+
+        assert!(
+            Conductor::from_preset("unobtainium", Shading::COLOR, 0.0).is_err()
+        );
+    }
+
+    #[test]
+    fn dielectric_beer_lambert() {
+        let mut glass =
+            Dielectric::new(arr1(&[1.0, 1.0, 1.0, 1.0]), Shading::COLOR, 1.5);
+        glass.absorption = Dielectric::absorption_from_transmittance(
+            &arr1(&[0.5, 1.0, 1.0, 1.0]),
+            1.0,
+        );
+
+        // Ray exiting the glass after traveling 2 units inside.
+        let incident =
+            Ray::new(arr1(&[0.0, 0.0, 0.0, 1.0]), arr1(&[0.0, 0.0, 1.0, 0.0]));
+        let mut hit = Hit::new();
+        hit.t = 2.0;
+        hit.point = arr1(&[0.0, 0.0, 2.0, 1.0]);
+        hit.normal = arr1(&[0.0, 0.0, 1.0, 0.0]);
+
+        let seeded = RandomSampler::seeded(0, 0);
+        sampler::with_sampler(Box::new(seeded), || {
+            let mut attenuation = arr1(&[0.0, 0.0, 0.0, 0.0]);
+            let mut scattered = Ray::new(hit.point.clone(), hit.normal.clone());
+            glass.scatter(&incident, &hit, &mut attenuation, &mut scattered);
+            assert!((attenuation[0] - 0.25).abs() < 1e-9);
+            assert!((attenuation[1] - 1.0).abs() < 1e-9);
+            assert_eq!(attenuation[3], 1.0);
+
+            // Entering the glass, nothing has been absorbed yet.
+            hit.normal = arr1(&[0.0, 0.0, -1.0, 0.0]);
+            glass.scatter(&incident, &hit, &mut attenuation, &mut scattered);
+            assert!((attenuation[0] - 1.0).abs() < 1e-9);
+        });
+    }
+
+    #[test]
+    fn dispersion_models() {
+        // Reference N-BK7 indices at the C, d and F Fraunhofer lines.
+        assert!((BK7.refraction_idx(656.3) - 1.5143).abs() < 1e-4);
+        assert!((BK7.refraction_idx(587.6) - 1.5168).abs() < 1e-4);
+        assert!((BK7.refraction_idx(486.1) - 1.5224).abs() < 1e-4);
+
+        let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.01 };
+        assert!((cauchy.refraction_idx(500.0) - 1.54).abs() < 1e-9);
+
+        let mut glass =
+            Dielectric::new(arr1(&[1.0, 1.0, 1.0, 1.0]), Shading::COLOR, 1.5);
+        assert_eq!(glass.refraction_idx_at(Some(500.0)), 1.5);
+        glass.dispersion = Some(cauchy);
+        assert_eq!(glass.refraction_idx_at(None), 1.5);
+        assert!((glass.refraction_idx_at(Some(500.0)) - 1.54).abs() < 1e-9);
+    }
+
+    #[test]
+    fn thin_film_limits() {
+        // Without thickness, the film does not change the reflectance.
+        let film = ThinFilm {
+            thickness: 0.0,
+            refraction_idx: 1.33,
+        };
+        for cosine in [1.0, 0.7, 0.2].iter() {
+            let (eta, k) = (0.27105, 3.6092);
+            let coated = thin_film_reflectance(
+                *cosine,
+                550.0,
+                &film,
+                1.0,
+                Complex64::new(eta, k),
+            );
+            assert!((coated - fresnel_conductor(*cosine, eta, k)).abs() < 1e-9);
+        }
+
+        // A soap bubble (film in air) reflects wavelengths differently.
+        let bubble = ThinFilm {
+            thickness: 400.0,
+            refraction_idx: 1.33,
+        };
+        let air = Complex64::new(1.0, 0.0);
+        let red = thin_film_reflectance(1.0, 630.0, &bubble, 1.0, air);
+        let green = thin_film_reflectance(1.0, 532.0, &bubble, 1.0, air);
+        assert!(red > 0.05 && green < 1e-6);
+
+        // Quarter-wave film at normal incidence: reflections cancel out when
+        // the film index is sqrt(n_base).
+        let coating = ThinFilm {
+            thickness: 550.0 / (4.0 * 1.5_f64.sqrt()),
+            refraction_idx: 1.5_f64.sqrt(),
+        };
+        let glass = Complex64::new(1.5, 0.0);
+        assert!(thin_film_reflectance(1.0, 550.0, &coating, 1.0, glass) < 1e-9);
+    }
+
+    #[test]
+    fn normal_mapping() {
+        use crate::raytracer::texture::ConstantTexture;
+
+        let base = Box::new(Lambertian::new(
+            arr1(&[1.0, 1.0, 1.0, 1.0]),
+            Shading::NORMALS,
+        ));
+        let mut hit = Hit::new();
+        hit.normal = arr1(&[0.0, 0.0, 1.0, 0.0]);
+        hit.tangent = arr1(&[1.0, 0.0, 0.0, 0.0]);
+        hit.bitangent = arr1(&[0.0, 1.0, 0.0, 0.0]);
+
+        // The flat normal map leaves the normal untouched.
+        let flat = NormalMapped::new(
+            base.clone(),
+            NormalDetail::NormalMap(Box::new(ConstantTexture::new(arr1(&[
+                0.5, 0.5, 1.0, 1.0,
+            ])))),
+        );
+        assert!((flat.perturb_normal(&hit) - &hit.normal)
+            .iter()
+            .all(|x| x.abs() < 1e-9));
+
+        // Tilted towards the tangent, which shows in the normals shading.
+        let tilted = NormalMapped::new(
+            base.clone(),
+            NormalDetail::NormalMap(Box::new(ConstantTexture::new(arr1(&[
+                1.0, 0.5, 1.0, 1.0,
+            ])))),
+        );
+        let normal = tilted.perturb_normal(&hit);
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        assert!((normal[0] - half).abs() < 1e-9);
+        assert!((normal[2] - half).abs() < 1e-9);
+        assert!((tilted.color(&hit)[0] - 0.5 * (half + 1.0)).abs() < 1e-9);
+
+        // A constant height does not bump.
+        let bumped = NormalMapped::new(
+            base,
+            NormalDetail::BumpMap {
+                height: Box::new(ConstantTexture::new(arr1(&[
+                    0.3, 0.3, 0.3, 1.0,
+                ]))),
+                scale: 1.0,
+            },
+        );
+        assert!((bumped.perturb_normal(&hit) - &hit.normal)
+            .iter()
+            .all(|x| x.abs() < 1e-9));
+    }
+
+    #[test]
+    fn subsurface_medium() {
+        let material = Subsurface::new(
+            arr1(&[0.9, 0.5, 0.1, 1.0]),
+            arr1(&[0.5, 0.25, 0.1, 1.0]),
+            1.3,
+            Shading::COLOR,
+        );
+        let medium = material.medium().unwrap();
+
+        assert!((medium.extinction[0] - 2.0).abs() < 1e-9);
+        assert!((medium.extinction[1] - 4.0).abs() < 1e-9);
+        assert!((medium.extinction[2] - 10.0).abs() < 1e-9);
+        assert!(medium.albedo == material.albedo);
+
+        // Decorators keep the medium, other materials have none.
+        let layered = Layered::new(Box::new(material), 1.5);
+        assert!(layered.medium().is_some());
+        assert!(Lambertian::new(arr1(&[1.0, 1.0, 1.0, 1.0]), Shading::COLOR)
+            .medium()
+            .is_none());
+    }
+
+    #[test]
+    fn principled_albedo() {
+        // Whatever the parameters (at their limits), lobe and angle, the
+        // material does not reflect more light than it receives.
+        let mut hit = Hit::new();
+        hit.point = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        hit.normal = arr1(&[0.0, 0.0, 1.0, 0.0]);
+        let seeded = RandomSampler::seeded(0, 0);
+        sampler::with_sampler(Box::new(seeded), || {
+            for parameters in 0..64 {
+                let parameter = |bit: u32| ((parameters >> bit) & 1) as f64;
+                let material = Principled {
+                    metallic: parameter(0),
+                    roughness: parameter(1),
+                    specular: parameter(2),
+                    sheen: parameter(3),
+                    clearcoat: parameter(4),
+                    transmission: parameter(5),
+                    ..Principled::new(
+                        arr1(&[0.9, 0.9, 0.9, 1.0]),
+                        Shading::COLOR,
+                    )
+                };
+                for cosine in [1.0_f64, 0.5, 0.01].iter() {
+                    let sine = (1.0 - cosine * cosine).sqrt();
+                    let incident = Ray::new(
+                        arr1(&[-sine, 0.0, *cosine, 1.0]),
+                        arr1(&[sine, 0.0, -cosine, 0.0]),
+                    );
+                    for _sample in 0..64 {
+                        let mut attenuation = arr1(&[0.0, 0.0, 0.0, 0.0]);
+                        let mut scattered =
+                            Ray::new(hit.point.clone(), hit.normal.clone());
+                        material.scatter(
+                            &incident,
+                            &hit,
+                            &mut attenuation,
+                            &mut scattered,
+                        );
+                        assert!(attenuation.iter().all(|x| *x <= 1.0));
+                    }
+                }
+            }
+        });
+    }
+}
@@ -2,35 +2,458 @@ pub mod actor;
 pub mod camera;
 pub mod common;
 pub mod common_testing;
+pub mod film;
+pub mod filter;
 pub mod image;
+pub mod integrator;
 pub mod material;
+pub mod photon;
+pub mod sampler;
 pub mod scenes;
+pub mod spectrum;
+pub mod texture;
 
 pub mod canvas {
-    extern crate rand;
-
-    use rand::Rng;
-
-    use crate::raytracer::actor::Hit;
-    use crate::raytracer::actor::Hittable;
     use crate::raytracer::actor::HittableList;
     use crate::raytracer::actor::RayTraceable;
     use crate::raytracer::camera::Camera;
-    use crate::raytracer::common::Ray;
-    use crate::raytracer::common::Vec4;
+    use crate::raytracer::film::Film;
+    use crate::raytracer::film::ToneMapper;
+    use crate::raytracer::filter::Filter;
+    use crate::raytracer::filter::FilterKind;
     use crate::raytracer::image::Image;
-    use crate::raytracer::image::Pixel;
+    use crate::raytracer::integrator::hue_to_rgb;
+    use crate::raytracer::integrator::Integrator;
+    use crate::raytracer::integrator::PathTracer;
+    use crate::raytracer::material::MaterialLibrary;
+    use crate::raytracer::sampler;
+    use crate::raytracer::sampler::RandomSampler;
+    use crate::raytracer::sampler::Sampling;
+    use crate::raytracer::spectrum;
     use ndarray::{arr1, Array1};
     use rayon::prelude::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
     use std::vec::Vec;
 
+    /**
+     * When spectral is enabled, every sample traces a single (randomly
+     * chosen) wavelength instead of RGB, which captures wavelength
+     * dependent effects such as dispersion. Samples are accumulated in XYZ
+     * and converted to RGB per pixel.
+     *
+     * The integrator computes the samples, path tracing by default (see
+     * the integrator module for the alternatives).
+     *
+     * Renders are reproducible: every pixel draws its random numbers from
+     * its own stream of the seed (see sampler), so the same seed gives the
+     * same image whatever the order (or threads) pixels are rendered in.
+     * The sampling selects how they are generated (low-discrepancy ones
+     * converge faster).
+     *
+     * With adaptive sampling, samples is the minimum per pixel (see
+     * Adaptive).
+     *
+     * Samples are accumulated (linear, per pixel) and the film is their
+     * average, the frame is its 8-bit image (developed with the tone
+     * mapper): progressive renders keep adding to them, every call
+     * improves the frame (for previews). Samples are splatted to the pixels
+     * around them with the reconstruction filter (see Filter), the average
+     * is weighted by it.
+     *
+     * Pixels are rendered by tiles, in the tile order, the on_tile callback
+     * is called (from the rendering thread) as each of them is done. A
+     * render can be cancelled with the cancel token, the tiles not started
+     * yet are skipped (integrators with their own render, see Integrator,
+     * are not split in tiles).
+     *
+     * Renders with options (see RenderOptions) report their progress, and
+     * can also be stopped by a token of their own or a time budget.
+     *
+     * Progressive renders can be saved to a checkpoint, and resumed from it
+     * later (by another Canvas of the same scene): the state of its
+     * sampler is its seed and passes, so the resumed render gives the same
+     * image as an uninterrupted one.
+     */
     pub struct Canvas {
         pub width: u32,
         pub height: u32,
         pub world: HittableList,
         pub samples: u32,
+        pub spectral: bool,
+        pub integrator: Box<dyn Integrator>,
+        pub seed: u64,
+        pub sampling: Sampling,
+        pub adaptive: Option<Adaptive>,
+        pub filter: Filter,
+        pub tone_mapper: ToneMapper,
+        pub tile_size: u32,
+        pub tile_order: TileOrder,
+        pub on_tile: Option<TileCallback>,
         camera: Camera,
-        image: Image,
+        film: Film,
+        accumulation: Vec<Array1<f64>>,
+        weights: Vec<f64>,
+        sample_counts: Vec<u32>,
+        passes: u64,
+        cancel: CancelToken,
+        cancelled: bool,
+        checkpointed: Instant,
+    }
+
+    /**
+     * Order the tiles are rendered in: by rows (Scanline), from the center
+     * outwards (Spiral, where the subject usually is), or along the Morton
+     * (Z-order) curve, which keeps consecutive tiles close to each other.
+     */
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub enum TileOrder {
+        Scanline,
+        #[default]
+        Spiral,
+        Morton,
+    }
+
+    /**
+     * Rectangle of pixels, rendered as a unit (or the crop window of a
+     * render, see RenderOptions).
+     */
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct Tile {
+        pub x: u32,
+        pub y: u32,
+        pub width: u32,
+        pub height: u32,
+    }
+
+    impl Tile {
+        pub fn contains(&self, x: u32, y: u32) -> bool {
+            self.x <= x
+                && x < self.x.saturating_add(self.width)
+                && self.y <= y
+                && y < self.y.saturating_add(self.height)
+        }
+
+        /** Pixels in both rectangles, if any. */
+        pub fn intersection(&self, other: &Tile) -> Option<Tile> {
+            let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+            let right = self
+                .x
+                .saturating_add(self.width)
+                .min(other.x.saturating_add(other.width));
+            let bottom = self
+                .y
+                .saturating_add(self.height)
+                .min(other.y.saturating_add(other.height));
+            if right <= x || bottom <= y {
+                return None;
+            }
+            Some(Tile {
+                x,
+                y,
+                width: right - x,
+                height: bottom - y,
+            })
+        }
+    }
+
+    /** Called with each tile once it is rendered, from the worker thread. */
+    pub type TileCallback = Box<dyn Fn(&Tile) + Send + Sync>;
+
+    /**
+     * Cancels the render in progress of the Canvas it comes from (from any
+     * thread).
+     */
+    #[derive(Clone, Default)]
+    pub struct CancelToken(Arc<AtomicBool>);
+
+    impl CancelToken {
+        pub fn cancel(&self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+
+        pub fn is_cancelled(&self) -> bool {
+            self.0.load(Ordering::Relaxed)
+        }
+
+        fn reset(&self) {
+            self.0.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /** Progress of a render, as reported to the progress callback. */
+    #[derive(Clone, Copy, Debug)]
+    pub struct Progress {
+        pub percent: f64,
+        pub samples: u64,
+        pub eta: Duration,
+    }
+
+    /** Called with the progress of a render, from the worker thread. */
+    pub type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+    /**
+     * Options of a render. The progress callback is called as each tile is
+     * done, with the percentage of the pixels rendered, the samples taken
+     * and the estimated time left. The render stops (between tiles, and
+     * between the samples of a pixel) once the token (or the Canvas one) is
+     * cancelled, or once the time budget (wall-clock, from the start of
+     * the render) is spent. The frame is then the estimate of the samples
+     * taken so far.
+     *
+     * With a crop window, only its pixels are rendered, the others keep
+     * their samples (see grab_crop for the cropped image). With a filter
+     * wider than a pixel, the ones on its border only get the samples from
+     * within.
+     */
+    #[derive(Default)]
+    pub struct RenderOptions {
+        pub on_progress: Option<ProgressCallback>,
+        pub cancel: Option<CancelToken>,
+        pub time_budget: Option<Duration>,
+        pub checkpoint: Option<Checkpoint>,
+        pub crop: Option<Tile>,
+    }
+
+    /**
+     * Where (and how often) progressive renders save their checkpoint: after
+     * a pass, once the interval since the last one has elapsed. Stopped
+     * passes are not saved, they cannot be resumed from.
+     */
+    #[derive(Clone, Debug)]
+    pub struct Checkpoint {
+        pub path: PathBuf,
+        pub interval: Duration,
+    }
+
+    /**
+     * Checkpoint files start with the magic (and version), followed by the
+     * width, height (u32), the settings of the render (see
+     * checkpoint_settings), seed and passes (u64), then by the sample count
+     * (u32), the sum of the filter weights (f64) and the accumulated color
+     * (4 f64) of every pixel, all little endian.
+     */
+    const CHECKPOINT_MAGIC: &[u8; 8] = b"SATCKPT3";
+    const CHECKPOINT_SETTINGS_SIZE: usize = 4 * 4 + 3 * 8;
+    const CHECKPOINT_HEADER_SIZE: usize =
+        8 + 2 * 4 + CHECKPOINT_SETTINGS_SIZE + 2 * 8;
+    const CHECKPOINT_PIXEL_SIZE: usize = 4 + 8 + 4 * 8;
+
+    /**
+     * Samples of a pixel in the pixels around it (the extent of the filter
+     * on each side, by rows): the sum of their colors weighted by the
+     * filter, and of the weights.
+     */
+    struct Splat {
+        extent: i64,
+        colors: Vec<Array1<f64>>,
+        weights: Vec<f64>,
+    }
+
+    impl Splat {
+        fn new(extent: i64) -> Splat {
+            let size = ((2 * extent + 1) * (2 * extent + 1)) as usize;
+            Splat {
+                extent,
+                colors: vec![arr1(&[0.0, 0.0, 0.0, 0.0]); size],
+                weights: vec![0.0; size],
+            }
+        }
+    }
+
+    /**
+     * State of a render in progress: whether it has to stop (which is
+     * latched, see stopped), and what it has done so far.
+     */
+    struct Control<'a> {
+        options: &'a RenderOptions,
+        cancel: &'a CancelToken,
+        start: Instant,
+        pixels: usize,
+        pixels_done: AtomicUsize,
+        samples_done: AtomicU64,
+        stopped: AtomicBool,
+    }
+
+    impl<'a> Control<'a> {
+        fn new(
+            options: &'a RenderOptions,
+            cancel: &'a CancelToken,
+            start: Instant,
+            pixels: usize,
+        ) -> Control<'a> {
+            Control {
+                options,
+                cancel,
+                start,
+                pixels,
+                pixels_done: AtomicUsize::new(0),
+                samples_done: AtomicU64::new(0),
+                stopped: AtomicBool::new(false),
+            }
+        }
+
+        /**
+         * Whether the render has to stop: cancelled or out of time. Once
+         * it is, it stays so.
+         */
+        fn stopped(&self) -> bool {
+            if self.stopped.load(Ordering::Relaxed) {
+                return true;
+            }
+            let stop = self.cancel.is_cancelled()
+                || self
+                    .options
+                    .cancel
+                    .as_ref()
+                    .is_some_and(|cancel| cancel.is_cancelled())
+                || self
+                    .options
+                    .time_budget
+                    .is_some_and(|budget| self.start.elapsed() >= budget);
+            if stop {
+                self.stopped.store(true, Ordering::Relaxed);
+            }
+            stop
+        }
+
+        /** Records (and reports) pixels done, with their samples. */
+        fn done(&self, pixels: usize, samples: u64) {
+            let pixels_done =
+                self.pixels_done.fetch_add(pixels, Ordering::Relaxed) + pixels;
+            let samples_done =
+                self.samples_done.fetch_add(samples, Ordering::Relaxed)
+                    + samples;
+            if let Some(on_progress) = &self.options.on_progress {
+                let fraction = pixels_done as f64 / self.pixels.max(1) as f64;
+                let elapsed = self.start.elapsed().as_secs_f64();
+                // Nothing done (e.g. an empty region), nothing to estimate.
+                let eta = if fraction > 0.0 {
+                    Duration::try_from_secs_f64(
+                        elapsed * (1.0 - fraction) / fraction,
+                    )
+                    .unwrap_or(Duration::ZERO)
+                } else {
+                    Duration::ZERO
+                };
+                on_progress(&Progress {
+                    percent: 100.0 * fraction,
+                    samples: samples_done,
+                    eta,
+                });
+            }
+        }
+    }
+
+    /**
+     * Tiles (of at most size x size pixels) covering the image, in the
+     * order.
+     */
+    pub fn tiles(
+        width: u32,
+        height: u32,
+        size: u32,
+        order: TileOrder,
+    ) -> Vec<Tile> {
+        if width == 0 || height == 0 {
+            return vec![];
+        }
+        let size = size.max(1);
+        let (columns, rows) = (width.div_ceil(size), height.div_ceil(size));
+        let mut tiles: Vec<(u32, u32)> = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect();
+
+        match order {
+            TileOrder::Scanline => {}
+            TileOrder::Spiral => {
+                // Ring by ring around the center, clockwise in each.
+                let (cx, cy) =
+                    ((columns - 1) as f64 / 2.0, (rows - 1) as f64 / 2.0);
+                let key = |(column, row): &(u32, u32)| {
+                    let (dx, dy) = (*column as f64 - cx, *row as f64 - cy);
+                    (dx.abs().max(dy.abs()), dy.atan2(dx))
+                };
+                tiles.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+            }
+            TileOrder::Morton => {
+                // Interleaved bits of the column (even) and row (odd).
+                let morton = |(column, row): &(u32, u32)| {
+                    (0..16).fold(0u64, |code, bit| {
+                        code | ((*column as u64 >> bit) & 1) << (2 * bit)
+                            | ((*row as u64 >> bit) & 1) << (2 * bit + 1)
+                    })
+                };
+                tiles.sort_by_key(morton);
+            }
+        }
+
+        tiles
+            .into_iter()
+            .map(|(column, row)| Tile {
+                x: column * size,
+                y: row * size,
+                width: size.min(width - column * size),
+                height: size.min(height - row * size),
+            })
+            .collect()
+    }
+
+    /**
+     * Samples a render adds to every pixel, with the seed they are drawn
+     * from.
+     */
+    #[derive(Clone, Copy)]
+    struct Pass {
+        seed: u64,
+        samples: u32,
+        adaptive: Option<Adaptive>,
+    }
+
+    /**
+     * Adaptive sampling: pixels are sampled in batches (of the Canvas
+     * samples) until the standard error of their mean luminance is below
+     * error (relative to the mean), or until max_samples, so that only the
+     * noisy ones keep being sampled.
+     */
+    #[derive(Clone, Copy, Debug)]
+    pub struct Adaptive {
+        pub max_samples: u32,
+        pub error: f64,
+    }
+
+    impl Pass {
+        /**
+         * Samples a pixel can take: whole batches of the samples, up to the
+         * maximum of adaptive sampling.
+         */
+        fn max_samples(&self) -> u32 {
+            let batch = self.samples.max(1);
+            match &self.adaptive {
+                Some(adaptive) => {
+                    adaptive.max_samples.max(batch).div_ceil(batch) * batch
+                }
+                None => self.samples,
+            }
+        }
+    }
+
+    impl Adaptive {
+        /**
+         * Whether the mean of the count luminance samples, with the sum of
+         * their squared differences to it (m2), is within the error.
+         */
+        fn converged(&self, count: u32, mean: f64, m2: f64) -> bool {
+            if count < 2 {
+                return false;
+            }
+            let variance = m2 / (count - 1) as f64;
+            let standard_error = (variance / count as f64).sqrt();
+            standard_error <= self.error * mean.abs().max(1e-3)
+        }
     }
 
     impl Canvas {
@@ -38,139 +461,677 @@ pub mod canvas {
             width: u32,
             height: u32,
             actors: Vec<Box<dyn RayTraceable>>,
+            materials: MaterialLibrary,
             samples: u32,
             camera: Camera,
         ) -> Canvas {
-            let world = HittableList::new(actors);
-            let image = Image::new(width, height, 4);
+            let world = HittableList::new(actors, materials);
+            let film = Film::new(width, height);
 
             Canvas {
                 width,
                 height,
                 world,
                 samples,
+                spectral: false,
+                integrator: Box::new(PathTracer::new()),
+                seed: 0,
+                sampling: Sampling::Random,
+                adaptive: None,
+                filter: Filter::default(),
+                tone_mapper: ToneMapper::default(),
                 camera,
-                image,
+                film,
+                accumulation: vec![
+                    arr1(&[0.0, 0.0, 0.0, 0.0]);
+                    (width * height) as usize
+                ],
+                weights: vec![0.0; (width * height) as usize],
+                sample_counts: vec![0; (width * height) as usize],
+                passes: 0,
+                tile_size: 16,
+                tile_order: TileOrder::Spiral,
+                on_tile: None,
+                cancel: CancelToken::default(),
+                cancelled: false,
+                checkpointed: Instant::now(),
             }
         }
 
         pub fn grab_frame(&self) -> Image {
-            self.image.clone()
+            self.film.to_image(&self.tone_mapper)
+        }
+
+        /** Linear radiance of the frame, in high dynamic range. */
+        pub fn grab_film(&self) -> Film {
+            self.film.clone()
         }
 
         /**
-         *  Compute the background color based on the ray direction.
-         *  Use LERP (linear interpolation), to generate a gradient on the
-         *  y-direction (similar to front-to-back blending).
+         * Error unless the crop window is a non-empty rectangle within the
+         * frame, e.g. to reject crop windows requested by users.
          */
-        fn background_color(&self, ray: &Ray) -> Array1<f64> {
-            let dir = Vec4::normalize(ray.direction.clone());
-            let param_y: f64 = 0.5 * (dir[1] + 1.0);
+        pub fn check_crop(&self, crop: &Tile) -> Result<(), String> {
+            if crop.width == 0 || crop.height == 0 {
+                return Err(format!("Crop window {:?} is empty.", crop));
+            }
+            if crop.intersection(&self.region(None)) != Some(*crop) {
+                return Err(format!(
+                    "Crop window {:?} is not within the {}x{} frame.",
+                    crop, self.width, self.height
+                ));
+            }
 
-            //let white = arr1(&[0.8, 0.8, 0.8, 0.9]);
-            //let blue = arr1(&[0.1, 0.2, 0.65, 0.9]);
-            let white = arr1(&[1.0, 1.0, 1.0, 1.0]);
-            let blue = arr1(&[0.5, 0.7, 1.0, 1.0]);
-            let color = (1.0 - param_y) * white + param_y * blue;
+            Ok(())
+        }
 
-            color
+        /** Pixels of the frame in the crop window (within the frame). */
+        pub fn grab_crop(&self, crop: &Tile) -> Image {
+            let crop = self.region(Some(crop));
+            self.grab_frame()
+                .crop(crop.x, crop.y, crop.width, crop.height)
         }
 
-        fn cast_rays(&self, ray: &Ray, depth: u32) -> Array1<f64> {
-            let current_hit = &mut Hit::new();
+        /** Token to cancel the renders of the Canvas. */
+        pub fn cancel_token(&self) -> CancelToken {
+            self.cancel.clone()
+        }
 
-            // Some of the reflected rays hit the object they are reflecting
-            // off of not at exactly t=0, but instead at t=-0.0000001 or
-            // t=0.00000001 or whatever floating point approximation the (sphere)
-            // intersector gives us. So we need to ignore hits very near zero and
-            // we do this by raising the minimum to 0.001.
-            if self.world.is_hit(ray, 0.0001, std::f64::MAX, current_hit) {
-                let mut attenuation = arr1(&[0.0, 0.0, 0.0, 1.0]);
-                let mut scattered = Ray::new(
-                    arr1(&[0.0, 0.0, 0.0, 1.0]),
-                    arr1(&[0.0, 0.0, 0.0, 0.0]),
-                );
+        /**
+         * Whether the last render was cancelled, or ran out of time (its
+         * skipped pixels have no samples, or only the ones of the previous
+         * passes).
+         */
+        pub fn was_cancelled(&self) -> bool {
+            self.cancelled
+        }
 
-                if current_hit.material.scatter(
-                    &ray,
-                    &current_hit,
-                    &mut attenuation,
-                    &mut scattered,
-                    depth,
-                ) {
-                    return attenuation * self.cast_rays(&scattered, depth + 1);
+        /** Samples accumulated in each pixel. */
+        pub fn sample_counts(&self) -> &[u32] {
+            &self.sample_counts
+        }
+
+        /**
+         * Heatmap of the samples accumulated in each pixel, from blue (the
+         * fewest) to red (the most).
+         */
+        pub fn grab_sample_counts(&self) -> Image {
+            let min = *self.sample_counts.iter().min().unwrap_or(&0);
+            let max = *self.sample_counts.iter().max().unwrap_or(&0);
+            let mut image = Image::new(self.width, self.height, 4);
+            for (pixel, count) in image.data.iter_mut().zip(&self.sample_counts)
+            {
+                let t = if max > min {
+                    (count - min) as f64 / (max - min) as f64
                 } else {
-                    return current_hit.material.color_noscatter(&current_hit);
-                }
-            } else {
-                return self.background_color(&ray);
+                    0.0
+                };
+                let color = hue_to_rgb(2.0 / 3.0 * (1.0 - t)) * 255.0;
+                pixel.data =
+                    [color[0] as u8, color[1] as u8, color[2] as u8, 255];
             }
+            image
         }
 
         pub fn render_scene_rayon(&mut self) {
-            let mut rendered_data = self.image.data.clone();
-            rendered_data
-                .par_iter_mut()
-                .enumerate()
-                .for_each(&self.render_pixel());
-            self.image.data = rendered_data;
+            self.render(true, &RenderOptions::default());
         }
 
         pub fn render_scene(&mut self) {
-            let mut rendered_data = self.image.data.clone();
-            rendered_data
-                .iter_mut()
-                .enumerate()
-                .for_each(self.render_pixel());
-            self.image.data = rendered_data;
+            self.render(false, &RenderOptions::default());
+        }
+
+        /** Same as render_scene_rayon, with the options. */
+        pub fn render_scene_with(&mut self, options: &RenderOptions) {
+            self.render(true, options);
         }
 
-        fn render_pixel(
+        /**
+         * Renders all the samples, replacing the accumulated ones.
+         */
+        fn render(&mut self, parallel: bool, options: &RenderOptions) {
+            self.cancel.reset();
+            let start = Instant::now();
+            self.preprocess();
+            let pass = Pass {
+                seed: self.seed,
+                samples: self.samples,
+                adaptive: self.adaptive,
+            };
+            let region = self.region(options.crop.as_ref());
+            let control = Control::new(
+                options,
+                &self.cancel,
+                start,
+                (region.width * region.height) as usize,
+            );
+            let pixels = self.render_pass(pass, parallel, &control, &region);
+            self.cancelled = control.stopped.into_inner();
+
+            // The pixels of the region are replaced (the skipped ones have
+            // no samples), the others are untouched.
+            for index in 0..self.sample_counts.len() {
+                let (x, y) = Image::pixel_coordinate(self.width, index);
+                if region.contains(x, y) {
+                    self.accumulation[index].fill(0.0);
+                    self.weights[index] = 0.0;
+                    self.sample_counts[index] = 0;
+                }
+            }
+            self.accumulate(pixels, &region);
+            self.passes = 1;
+            self.update_film();
+        }
+
+        /**
+         * Adds samples to every pixel (without adaptive sampling), and
+         * updates the frame with the current estimate. The first pass
+         * (after a reset) is the same as render_scene, each of the others
+         * draws from a new seed derived from the Canvas one.
+         */
+        pub fn render_progressive(&mut self, samples: u32) {
+            self.progressive_pass(samples, &RenderOptions::default());
+        }
+
+        /**
+         * Same as render_progressive, with the options, which may save a
+         * checkpoint once the pass is done.
+         */
+        pub fn render_progressive_with(
             &mut self,
-        ) -> Box<dyn Fn((usize, &mut Pixel<u8>)) -> () + '_ + Sync> {
-            return Box::new(move |(index, pixel)| {
-                let (x, y) = Image::pixel_coordinate(self.image.width, index);
-                let mut color = arr1(&[0.0, 0.0, 0.0, 0.0]);
+            samples: u32,
+            options: &RenderOptions,
+        ) -> Result<(), String> {
+            self.progressive_pass(samples, options);
 
-                color = self.compute_samples(color, x, y);
-                color = color / self.samples as f64;
+            match &options.checkpoint {
+                Some(checkpoint)
+                    if !self.cancelled
+                        && self.checkpointed.elapsed()
+                            >= checkpoint.interval =>
+                {
+                    self.save_checkpoint(&checkpoint.path)
+                }
+                _ => Ok(()),
+            }
+        }
 
-                self.gamma_correct(&mut color, 2.0);
-                color = color * 255.0;
+        fn progressive_pass(&mut self, samples: u32, options: &RenderOptions) {
+            self.cancel.reset();
+            let start = Instant::now();
+            if self.passes == 0 {
+                self.preprocess();
+            }
+            let pass = Pass {
+                seed: match self.passes {
+                    0 => self.seed,
+                    passes => sampler::mix(self.seed, passes),
+                },
+                samples,
+                adaptive: None,
+            };
+            let region = self.region(options.crop.as_ref());
+            let control = Control::new(
+                options,
+                &self.cancel,
+                start,
+                (region.width * region.height) as usize,
+            );
+            let pixels = self.render_pass(pass, true, &control, &region);
+            self.cancelled = control.stopped.into_inner();
 
-                pixel.data =
-                    [color[0] as u8, color[1] as u8, color[2] as u8, 255];
+            self.accumulate(pixels, &region);
+            self.passes += 1;
+            self.update_film();
+        }
+
+        /**
+         * Discards the accumulated samples, e.g. when the scene changed, the
+         * next progressive render starts over.
+         */
+        pub fn reset_progressive(&mut self) {
+            for sum in self.accumulation.iter_mut() {
+                sum.fill(0.0);
+            }
+            self.weights.fill(0.0);
+            self.sample_counts.fill(0);
+            self.passes = 0;
+            self.checkpointed = Instant::now();
+            self.update_film();
+        }
+
+        /**
+         * Settings the accumulated samples depend on, besides the size, as
+         * checkpoints store them: samples, sampling and spectral (u32), the
+         * kind of filter (u32), its parameters (2 f64, 0 when unused) and
+         * its radius (f64).
+         */
+        fn checkpoint_settings(&self) -> Vec<u8> {
+            let sampling: u32 = match self.sampling {
+                Sampling::Random => 0,
+                Sampling::Stratified => 1,
+                Sampling::Halton => 2,
+                Sampling::Sobol => 3,
+                Sampling::BlueNoise => 4,
+            };
+            let (kind, first, second): (u32, f64, f64) = match self.filter.kind
+            {
+                FilterKind::Box => (0, 0.0, 0.0),
+                FilterKind::Tent => (1, 0.0, 0.0),
+                FilterKind::Gaussian { alpha } => (2, alpha, 0.0),
+                FilterKind::Mitchell { b, c } => (3, b, c),
+                FilterKind::Lanczos { tau } => (4, tau, 0.0),
+            };
+
+            let mut bytes = Vec::with_capacity(CHECKPOINT_SETTINGS_SIZE);
+            for value in
+                [self.samples, sampling, self.spectral as u32, kind].iter()
+            {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in [first, second, self.filter.radius].iter() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes
+        }
+
+        /**
+         * Saves the accumulated samples (and the state of the sampler) to
+         * the file, see CHECKPOINT_MAGIC for the format. It is written next
+         * to it first (with a .tmp suffix), and then renamed, so that an
+         * interrupted save keeps the previous checkpoint.
+         */
+        pub fn save_checkpoint(&mut self, path: &Path) -> Result<(), String> {
+            let mut bytes = Vec::with_capacity(
+                CHECKPOINT_HEADER_SIZE
+                    + self.sample_counts.len() * CHECKPOINT_PIXEL_SIZE,
+            );
+            bytes.extend_from_slice(CHECKPOINT_MAGIC);
+            bytes.extend_from_slice(&self.width.to_le_bytes());
+            bytes.extend_from_slice(&self.height.to_le_bytes());
+            bytes.extend_from_slice(&self.checkpoint_settings());
+            bytes.extend_from_slice(&self.seed.to_le_bytes());
+            bytes.extend_from_slice(&self.passes.to_le_bytes());
+            let pixels = self
+                .accumulation
+                .iter()
+                .zip(self.weights.iter())
+                .zip(self.sample_counts.iter());
+            for ((sum, weight), count) in pixels {
+                bytes.extend_from_slice(&count.to_le_bytes());
+                bytes.extend_from_slice(&weight.to_le_bytes());
+                for channel in sum.iter() {
+                    bytes.extend_from_slice(&channel.to_le_bytes());
+                }
+            }
+
+            let mut temporary = path.as_os_str().to_owned();
+            temporary.push(".tmp");
+            let temporary = PathBuf::from(temporary);
+            fs::write(&temporary, bytes)
+                .and_then(|_| fs::rename(&temporary, path))
+                .map_err(|error| {
+                    format!("Cannot save checkpoint {:?}: {}", path, error)
+                })?;
+            self.checkpointed = Instant::now();
+            Ok(())
+        }
+
+        /**
+         * Restores the accumulated samples (and the state of the sampler)
+         * from the file, progressive renders then resume from it. The
+         * checkpoint must be of the same size and settings (see
+         * checkpoint_settings), the seed is the one it was rendered with.
+         */
+        pub fn load_checkpoint(&mut self, path: &Path) -> Result<(), String> {
+            let bytes = fs::read(path).map_err(|error| {
+                format!("Cannot read checkpoint {:?}: {}", path, error)
+            })?;
+            let pixels = self.sample_counts.len();
+            if bytes.len()
+                != CHECKPOINT_HEADER_SIZE + pixels * CHECKPOINT_PIXEL_SIZE
+                || &bytes[0..8] != CHECKPOINT_MAGIC
+            {
+                return Err(format!("Invalid checkpoint {:?}.", path));
+            }
+
+            let mut offset = 8;
+            let mut next = |size: usize| {
+                offset += size;
+                &bytes[offset - size..offset]
+            };
+            let u32_at = |bytes: &[u8]| {
+                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            };
+            let u64_at = |bytes: &[u8]| {
+                let mut value = [0; 8];
+                value.copy_from_slice(bytes);
+                u64::from_le_bytes(value)
+            };
+            if u32_at(next(4)) != self.width || u32_at(next(4)) != self.height {
+                return Err(format!(
+                    "Checkpoint {:?} is not of a {}x{} image.",
+                    path, self.width, self.height
+                ));
+            }
+            if next(CHECKPOINT_SETTINGS_SIZE)
+                != self.checkpoint_settings().as_slice()
+            {
+                return Err(format!(
+                    "Checkpoint {:?} was rendered with other samples, \
+                     sampling, filter or spectral setting.",
+                    path
+                ));
+            }
+            let seed = u64_at(next(8));
+            let passes = u64_at(next(8));
+            let mut accumulation = Vec::with_capacity(pixels);
+            let mut weights = Vec::with_capacity(pixels);
+            let mut sample_counts = Vec::with_capacity(pixels);
+            for _pixel in 0..pixels {
+                sample_counts.push(u32_at(next(4)));
+                weights.push(f64::from_bits(u64_at(next(8))));
+                let sum: Vec<f64> = (0..4)
+                    .map(|_channel| f64::from_bits(u64_at(next(8))))
+                    .collect();
+                accumulation.push(Array1::from(sum));
+            }
+
+            self.seed = seed;
+            self.accumulation = accumulation;
+            self.weights = weights;
+            self.sample_counts = sample_counts;
+            self.passes = passes;
+            // The integrator is preprocessed as it was for the first pass.
+            self.preprocess();
+            self.checkpointed = Instant::now();
+            self.update_film();
+            Ok(())
+        }
+
+        /**
+         * Adds the splats of the pixels (in the order of their index, so
+         * that the sums are the same whatever the order they were rendered
+         * in) to the ones of the region, and their samples to the counts.
+         */
+        fn accumulate(
+            &mut self,
+            mut pixels: Vec<(usize, (Splat, u32))>,
+            region: &Tile,
+        ) {
+            pixels.sort_by_key(|(index, _pixel)| *index);
+            for (index, (splat, count)) in pixels {
+                self.sample_counts[index] += count;
+
+                let (x, y) = Image::pixel_coordinate(self.width, index);
+                let size = 2 * splat.extent + 1;
+                let cells = splat.colors.iter().zip(splat.weights.iter());
+                for (cell, (color, weight)) in cells.enumerate() {
+                    let target_x = x as i64 + cell as i64 % size - splat.extent;
+                    let target_y = y as i64 + cell as i64 / size - splat.extent;
+                    if target_x < 0
+                        || target_y < 0
+                        || !region.contains(target_x as u32, target_y as u32)
+                    {
+                        continue;
+                    }
+                    let target =
+                        (target_y * self.width as i64 + target_x) as usize;
+                    let sum = &mut self.accumulation[target];
+                    *sum = &*sum + color;
+                    self.weights[target] += weight;
+                }
+            }
+        }
+
+        /** The crop window within the frame, or the whole frame. */
+        fn region(&self, crop: Option<&Tile>) -> Tile {
+            let frame = Tile {
+                x: 0,
+                y: 0,
+                width: self.width,
+                height: self.height,
+            };
+            match crop {
+                Some(crop) => crop.intersection(&frame).unwrap_or_default(),
+                None => frame,
+            }
+        }
+
+        /**
+         * Preprocessing of the integrator, with a stream of the seed of its
+         * own (pixels use the first ones, see render_pass).
+         */
+        fn preprocess(&mut self) {
+            let sampler = RandomSampler::seeded(self.seed, u64::MAX);
+            let (integrator, world) = (&mut self.integrator, &self.world);
+            sampler::with_sampler(Box::new(sampler), || {
+                integrator.preprocess(world)
             });
         }
 
+        /**
+         * Splats of the samples of the pass in the pixels of the region
+         * that were rendered, and their count, by index. Pixels draw from
+         * the stream of their index (plus one) of the seed. Integrators
+         * with their own render (see Integrator) render the whole image,
+         * from stream 0, and are not filtered.
+         */
+        fn render_pass(
+            &self,
+            pass: Pass,
+            parallel: bool,
+            control: &Control,
+            region: &Tile,
+        ) -> Vec<(usize, (Splat, u32))> {
+            let sampler = RandomSampler::seeded(pass.seed, 0);
+            let image = sampler::with_sampler(Box::new(sampler), || {
+                self.integrator
+                    .render(&self.world, &self.camera, pass.samples)
+            });
+            if let Some(colors) = image {
+                let pixels: Vec<_> = colors
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(index, _color)| {
+                        let (x, y) =
+                            Image::pixel_coordinate(self.width, *index);
+                        region.contains(x, y)
+                    })
+                    .map(|(index, color)| {
+                        let mut splat = Splat::new(0);
+                        splat.colors[0] = color * pass.samples as f64;
+                        splat.weights[0] = pass.samples as f64;
+                        (index, (splat, pass.samples))
+                    })
+                    .collect();
+                control.done(
+                    pixels.len(),
+                    pixels.len() as u64 * pass.samples as u64,
+                );
+                return pixels;
+            }
+
+            let render_pixel = |index: usize| {
+                let (x, y) = Image::pixel_coordinate(self.width, index);
+                let sampler = sampler::pixel_sampler(
+                    self.sampling,
+                    pass.seed,
+                    index as u64 + 1,
+                    x,
+                    y,
+                    pass.max_samples(),
+                );
+                sampler::with_sampler(sampler, || {
+                    self.compute_samples(x, y, &pass, control)
+                })
+            };
+
+            // The region is split in tiles, workers take the next one until
+            // there is none left (or the render is stopped), so tiles start
+            // in order.
+            let tiles: Vec<Tile> = tiles(
+                region.width,
+                region.height,
+                self.tile_size,
+                self.tile_order,
+            )
+            .into_iter()
+            .map(|tile| Tile {
+                x: region.x + tile.x,
+                y: region.y + tile.y,
+                ..tile
+            })
+            .collect();
+            let next = AtomicUsize::new(0);
+            let render_tiles = || {
+                let mut rendered = Vec::new();
+                while !control.stopped() {
+                    let tile =
+                        match tiles.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            Some(tile) => tile,
+                            None => break,
+                        };
+                    let mut samples = 0;
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            let index = (y * self.width + x) as usize;
+                            let pixel = render_pixel(index);
+                            samples += pixel.1 as u64;
+                            rendered.push((index, pixel));
+                        }
+                    }
+                    if let Some(on_tile) = &self.on_tile {
+                        on_tile(tile);
+                    }
+                    control.done((tile.width * tile.height) as usize, samples);
+                }
+                rendered
+            };
+            if parallel {
+                (0..rayon::current_num_threads())
+                    .into_par_iter()
+                    .flat_map(|_worker| render_tiles())
+                    .collect()
+            } else {
+                render_tiles()
+            }
+        }
+
+        /** Film of the (weighted) average of the accumulated samples. */
+        fn update_film(&mut self) {
+            let pixels = self.accumulation.iter().zip(self.weights.iter());
+            for (pixel, (sum, weight)) in self.film.data.iter_mut().zip(pixels)
+            {
+                pixel.data = if *weight > 0.0 {
+                    [sum[0], sum[1], sum[2], sum[3]].map(|x| x / *weight)
+                } else {
+                    [0.0, 0.0, 0.0, 0.0]
+                };
+            }
+        }
+
+        /**
+         * Splat of the samples of the pixel, and their count. They are
+         * taken in batches (of samples), more of them only while adaptive
+         * sampling has not converged, and none once the render is stopped.
+         */
         fn compute_samples(
             &self,
-            mut color: Array1<f64>,
             x: u32,
             y: u32,
-        ) -> Array1<f64> {
-            let mut rng = rand::thread_rng();
-
-            // TODO review why the statement below produces weird results...
-            // for i in 0..=number_samples {
-            for sample in 0..self.samples {
-                let mut x_final = x as f64;
-                let mut y_final = y as f64;
-
-                if sample > 0 {
-                    x_final = x as f64 + rng.gen_range(0.0, 0.999999);
-                    y_final = y as f64 + rng.gen_range(0.0, 0.999999);
+            pass: &Pass,
+            control: &Control,
+        ) -> (Splat, u32) {
+            let batch = pass.samples.max(1);
+            let extent = self.filter.extent();
+            let size = 2 * extent + 1;
+            let mut splat = Splat::new(extent);
+            let mut count = 0;
+            // Running mean (and squared differences) of the luminance.
+            let (mut mean, mut m2) = (0.0, 0.0);
+
+            'batches: while count < pass.max_samples() {
+                for stratum in 0..batch {
+                    if control.stopped() {
+                        break 'batches;
+                    }
+                    let (position, sample) =
+                        self.compute_sample(x, y, count, stratum, batch);
+                    let luminance = if self.spectral {
+                        sample[1]
+                    } else {
+                        spectrum::luminance(&sample)
+                    };
+                    let cells =
+                        splat.colors.iter_mut().zip(splat.weights.iter_mut());
+                    for (cell, (color, total)) in cells.enumerate() {
+                        // Offset of the sample from the center of the pixel.
+                        let dx = (cell as i64 % size - extent) as f64 + 0.5
+                            - position.0;
+                        let dy = (cell as i64 / size - extent) as f64 + 0.5
+                            - position.1;
+                        let weight = self.filter.evaluate(dx, dy);
+                        if weight != 0.0 {
+                            *color = &*color + &(&sample * weight);
+                            *total += weight;
+                        }
+                    }
+
+                    count += 1;
+                    let delta = luminance - mean;
+                    mean += delta / count as f64;
+                    m2 += delta * (luminance - mean);
                 }
 
-                let ray = self.camera.get_ray(x_final, y_final);
+                match &pass.adaptive {
+                    Some(adaptive) if !adaptive.converged(count, mean, m2) => {}
+                    _ => break,
+                }
+            }
 
-                color = color + self.cast_rays(&ray, 1);
+            if self.spectral {
+                for color in splat.colors.iter_mut() {
+                    *color = spectrum::xyz_to_rgb(color);
+                }
             }
-            color
+            (splat, count)
         }
 
-        fn gamma_correct(&self, color: &mut Array1<f64>, gamma: f64) {
-            color.mapv_inplace(|x| x.powf(1.0 / gamma));
+        /**
+         * Position of the sample in the pixel, and the sample, in XYZ in
+         * spectral mode. The stratum is its index in the batch.
+         */
+        fn compute_sample(
+            &self,
+            x: u32,
+            y: u32,
+            sample: u32,
+            stratum: u32,
+            batch: u32,
+        ) -> ((f64, f64), Array1<f64>) {
+            // The first two dimensions of the sample are its position in
+            // the pixel.
+            sampler::start_sample(sample as u64);
+            let position = (sampler::next_1d(), sampler::next_1d());
+            let x_final = x as f64 + position.0;
+            let y_final = y as f64 + position.1;
+
+            let mut ray = self.camera.get_ray(x_final, y_final);
+
+            if self.spectral {
+                // Wavelengths are stratified over the samples of the batch,
+                // which removes most of the color noise.
+                let (wavelength, pdf) = spectrum::sample_wavelength(
+                    (stratum as f64 + sampler::next_1d()) / batch as f64,
+                );
+                ray.wavelength = Some(wavelength);
+
+                let radiance = self.integrator.radiance(&self.world, &ray)[0];
+                (position, spectrum::to_xyz(radiance, wavelength, pdf))
+            } else {
+                (position, self.integrator.radiance(&self.world, &ray))
+            }
         }
     }
 }
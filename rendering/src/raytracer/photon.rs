@@ -0,0 +1,277 @@
+use ndarray::{s, Array1};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/**
+ * Photon stored on a surface: where it landed, the (unit) direction it was
+ * travelling in, and the power (flux) it carries.
+ */
+#[derive(Clone)]
+pub struct Photon {
+    pub position: Array1<f64>,
+    pub direction: Array1<f64>,
+    pub power: Array1<f64>,
+}
+
+impl Photon {
+    pub fn new(
+        position: Array1<f64>,
+        direction: Array1<f64>,
+        power: Array1<f64>,
+    ) -> Photon {
+        Photon {
+            position,
+            direction,
+            power,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Photons in a kd-tree, for nearest neighbour queries. The tree is implicit:
+ * every range of photons is split at its middle one (the node), along the
+ * axis the range extends the most (stored per node), with the photons
+ * before / after it on the lower / upper side.
+ *
+ * Reference:
+ * * Jensen, "Realistic Image Synthesis Using Photon Mapping", 2001,
+ *   chapter 6.
+ */
+#[derive(Clone, Default)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+}
+
+/**
+ * Neighbour found by a query, ordered by distance (the farthest is on top
+ * of the heap).
+ */
+struct Neighbour {
+    distance2: f64,
+    index: usize,
+}
+
+impl PartialEq for Neighbour {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance2 == other.distance2
+    }
+}
+
+impl Eq for Neighbour {}
+
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance2
+            .partial_cmp(&other.distance2)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> PhotonMap {
+        let mut axes = vec![0; photons.len()];
+        PhotonMap::build(&mut photons, &mut axes);
+
+        PhotonMap { photons, axes }
+    }
+
+    fn build(photons: &mut [Photon], axes: &mut [usize]) {
+        if photons.is_empty() {
+            return;
+        }
+
+        let mut min = [f64::MAX; 3];
+        let mut max = [f64::MIN; 3];
+        for photon in photons.iter() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(photon.position[axis]);
+                max[axis] = max[axis].max(photon.position[axis]);
+            }
+        }
+        let axis = (0..3)
+            .max_by(|a, b| {
+                (max[*a] - min[*a])
+                    .partial_cmp(&(max[*b] - min[*b]))
+                    .unwrap_or(Ordering::Equal)
+            })
+            .unwrap();
+
+        let middle = photons.len() / 2;
+        photons.select_nth_unstable_by(middle, |a, b| {
+            a.position[axis]
+                .partial_cmp(&b.position[axis])
+                .unwrap_or(Ordering::Equal)
+        });
+        axes[middle] = axis;
+
+        let (photons_lower, photons_upper) = photons.split_at_mut(middle);
+        let (axes_lower, axes_upper) = axes.split_at_mut(middle);
+        PhotonMap::build(photons_lower, axes_lower);
+        PhotonMap::build(&mut photons_upper[1..], &mut axes_upper[1..]);
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /**
+     * The (at most) count photons nearest to the point, within max_radius
+     * of it, and the squared radius they are found in: the distance to the
+     * farthest of them, or max_radius when there are less than count.
+     */
+    pub fn nearest(
+        &self,
+        point: &Array1<f64>,
+        count: usize,
+        max_radius: f64,
+    ) -> (Vec<&Photon>, f64) {
+        let mut heap = BinaryHeap::with_capacity(count + 1);
+        let mut radius2 = max_radius * max_radius;
+        if count > 0 {
+            self.search(
+                0,
+                self.photons.len(),
+                point,
+                count,
+                &mut radius2,
+                &mut heap,
+            );
+        }
+
+        let photons = heap
+            .into_iter()
+            .map(|neighbour| &self.photons[neighbour.index])
+            .collect();
+        (photons, radius2)
+    }
+
+    /**
+     * Visits the node of the range, then the side of it the point is in,
+     * and the other one only when it is closer than the current radius.
+     * Once count photons are found, the radius shrinks to the farthest.
+     */
+    fn search(
+        &self,
+        begin: usize,
+        end: usize,
+        point: &Array1<f64>,
+        count: usize,
+        radius2: &mut f64,
+        heap: &mut BinaryHeap<Neighbour>,
+    ) {
+        if begin >= end {
+            return;
+        }
+
+        let middle = begin + (end - begin) / 2;
+        let photon = &self.photons[middle];
+        let axis = self.axes[middle];
+        let delta = point[axis] - photon.position[axis];
+        let (near, far) = if delta < 0.0 {
+            ((begin, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (begin, middle))
+        };
+
+        self.search(near.0, near.1, point, count, radius2, heap);
+
+        let offset = &photon.position.slice(s![0..3]) - &point.slice(s![0..3]);
+        let distance2 = offset.dot(&offset);
+        if distance2 < *radius2 {
+            heap.push(Neighbour {
+                distance2,
+                index: middle,
+            });
+            if heap.len() > count {
+                heap.pop();
+            }
+            if heap.len() == count {
+                *radius2 = heap.peek().unwrap().distance2;
+            }
+        }
+
+        if delta * delta < *radius2 {
+            self.search(far.0, far.1, point, count, radius2, heap);
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Tests
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::arr1;
+    use rand::Rng;
+
+    #[test]
+    fn photon_map_nearest() {
+        let mut rng = rand::thread_rng();
+        let photons: Vec<Photon> = (0..2000)
+            .map(|_| {
+                Photon::new(
+                    arr1(&[
+                        rng.gen_range(-1.0, 1.0),
+                        rng.gen_range(-1.0, 1.0),
+                        rng.gen_range(-1.0, 1.0),
+                        1.0,
+                    ]),
+                    arr1(&[0.0, -1.0, 0.0, 0.0]),
+                    arr1(&[1.0, 1.0, 1.0, 1.0]),
+                )
+            })
+            .collect();
+        let map = PhotonMap::new(photons.clone());
+        assert!(map.len() == 2000);
+
+        let distance2 = |a: &Array1<f64>, b: &Array1<f64>| {
+            (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum::<f64>()
+        };
+
+        for _ in 0..20 {
+            let point = arr1(&[
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+                1.0,
+            ]);
+
+            // Same as sorting all of them by distance (brute force).
+            let mut expected: Vec<f64> = photons
+                .iter()
+                .map(|photon| distance2(&photon.position, &point))
+                .collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+            let (found, radius2) = map.nearest(&point, 10, 10.0);
+            let mut found: Vec<f64> = found
+                .iter()
+                .map(|photon| distance2(&photon.position, &point))
+                .collect();
+            found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert!(found == expected[..10].to_vec());
+            assert!(radius2 == expected[9]);
+
+            // Fewer within a small radius.
+            let (found, radius2) = map.nearest(&point, 10, 0.1);
+            let within = expected.iter().filter(|d| **d < 0.01).count();
+            assert!(found.len() == within.min(10));
+            if within < 10 {
+                assert!(radius2 == 0.1 * 0.1);
+            }
+        }
+    }
+}
@@ -0,0 +1,786 @@
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+/**
+ * Source of the random numbers used while rendering. Every random decision
+ * (lens position, scattering direction, ...) is drawn from the sampler of the
+ * current thread, so an integrator can control them by installing its own
+ * one (see with_sampler). Threads have none until one is installed.
+ */
+pub trait Sampler {
+    /** Next number, uniformly distributed in [0, 1). */
+    fn next(&mut self) -> f64;
+
+    /**
+     * Starts the given sample of the pixel (the Canvas calls it before each
+     * of them): low-discrepancy samplers draw the dimensions of its point
+     * from there.
+     */
+    fn start_sample(&mut self, _sample: u64) {}
+}
+
+/**
+ * Independent uniform random numbers. Seeded ones
+ * always draw the same numbers, one stream per (seed, stream) pair (e.g.
+ * the seed of the render and the index of a pixel).
+ */
+pub struct RandomSampler {
+    rng: Pcg64,
+}
+
+impl RandomSampler {
+    pub fn new() -> RandomSampler {
+        RandomSampler {
+            rng: Pcg64::from_rng(rand::thread_rng()).unwrap(),
+        }
+    }
+
+    pub fn seeded(seed: u64, stream: u64) -> RandomSampler {
+        RandomSampler {
+            rng: Pcg64::seed_from_u64(mix(seed, stream)),
+        }
+    }
+}
+
+/**
+ * Combines a seed and a stream into a new seed, so that close values
+ * (consecutive pixels) give unrelated ones (SplitMix64 finalizer).
+ */
+pub fn mix(seed: u64, stream: u64) -> u64 {
+    let mut z = seed ^ stream.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl Default for RandomSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn next(&mut self) -> f64 {
+        self.rng.gen_range(0.0, 1.0)
+    }
+}
+
+/**
+ * A shared sampler, so that the one installed in the thread can still be
+ * driven (mutated, accepted, ...) by its owner.
+ */
+impl<S: Sampler> Sampler for Rc<RefCell<S>> {
+    fn next(&mut self) -> f64 {
+        self.borrow_mut().next()
+    }
+
+    fn start_sample(&mut self, sample: u64) {
+        self.borrow_mut().start_sample(sample)
+    }
+}
+
+/**
+ * Sampler of the threads that have not installed one. Drawing from it is a
+ * bug: the numbers would not be reproducible (e.g. in a rayon worker that
+ * did not install the sampler of its task), so it panics instead.
+ */
+struct Unset;
+
+impl Sampler for Unset {
+    fn next(&mut self) -> f64 {
+        panic!("no sampler installed in the thread, see with_sampler")
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Box<dyn Sampler>> = RefCell::new(Box::new(Unset));
+}
+
+/** Next number of the sampler of the thread, in [0, 1). */
+pub fn next_1d() -> f64 {
+    CURRENT.with(|current| current.borrow_mut().next())
+}
+
+/** Starts the given sample of the pixel, see Sampler. */
+pub fn start_sample(sample: u64) {
+    CURRENT.with(|current| current.borrow_mut().start_sample(sample))
+}
+
+/** Next number of the sampler of the thread, in [min, max). */
+pub fn gen_range(min: f64, max: f64) -> f64 {
+    min + (max - min) * next_1d()
+}
+
+/**
+ * Seed drawn from the sampler of the thread, for the samplers of work done
+ * in other threads (which have their own).
+ */
+pub fn next_seed() -> u64 {
+    (next_1d() * (1u64 << 53) as f64) as u64
+}
+
+/** Next index of the sampler of the thread, in [0, count). */
+pub fn gen_index(count: usize) -> usize {
+    ((next_1d() * count as f64) as usize).min(count - 1)
+}
+
+/**
+ * Restores the previous sampler of the thread when dropped (also when
+ * unwinding).
+ */
+struct Restore(Option<Box<dyn Sampler>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        if let Some(previous) = self.0.take() {
+            CURRENT.with(|current| current.replace(previous));
+        }
+    }
+}
+
+/**
+ * Runs f with sampler as the sampler of the thread, then restores the
+ * previous one.
+ */
+pub fn with_sampler<R>(sampler: Box<dyn Sampler>, f: impl FnOnce() -> R) -> R {
+    let _restore =
+        Restore(Some(CURRENT.with(|current| current.replace(sampler))));
+    f()
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Sample of the primary sample space, with the value it had before the
+ * current iteration (restored when the mutation is rejected).
+ */
+#[derive(Clone, Default)]
+struct PrimarySample {
+    value: f64,
+    modified: u64,
+    backup: f64,
+    modified_backup: u64,
+}
+
+/**
+ * Sampler in primary sample space, for Metropolis light transport: the
+ * numbers drawn are the coordinates of a point (a path) that is mutated
+ * every iteration, either entirely (large step) or by a small perturbation.
+ * Coordinates are mutated lazily, when they are drawn.
+ *
+ * Reference:
+ * * Kelemen et al., "A Simple and Robust Mutation Strategy for the
+ *   Metropolis Light Transport Algorithm", 2002.
+ * * Pharr et al., "Physically Based Rendering", 3rd edition, section 16.4.
+ */
+pub struct MetropolisSampler {
+    rng: Pcg64,
+    samples: Vec<PrimarySample>,
+    large_step_probability: f64,
+    sigma: f64,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+    index: usize,
+}
+
+impl MetropolisSampler {
+    pub fn new(
+        seed: u64,
+        large_step_probability: f64,
+        sigma: f64,
+    ) -> MetropolisSampler {
+        MetropolisSampler {
+            rng: Pcg64::seed_from_u64(seed),
+            samples: Vec::new(),
+            large_step_probability,
+            sigma,
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+            index: 0,
+        }
+    }
+
+    /** Mutates the point, drawing starts again from its first coordinate. */
+    pub fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step =
+            self.rng.gen_range(0.0, 1.0) < self.large_step_probability;
+        self.index = 0;
+    }
+
+    /** Keeps the mutated point. */
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    /** Goes back to the point before the mutation. */
+    pub fn reject(&mut self) {
+        let iteration = self.iteration;
+        for sample in self.samples.iter_mut() {
+            if sample.modified == iteration {
+                sample.value = sample.backup;
+                sample.modified = sample.modified_backup;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    /**
+     * Brings the coordinate up to date: resampled if it was not drawn since
+     * the last accepted large step, then mutated by this iteration (small
+     * steps it missed are applied at once, as a normal perturbation of
+     * their combined deviation).
+     */
+    fn mutate(&mut self, index: usize) {
+        let sample = &mut self.samples[index];
+        if sample.modified < self.last_large_step {
+            sample.value = self.rng.gen_range(0.0, 1.0);
+            sample.modified = self.last_large_step;
+        }
+
+        sample.backup = sample.value;
+        sample.modified_backup = sample.modified;
+        if self.large_step {
+            sample.value = self.rng.gen_range(0.0, 1.0);
+        } else {
+            let steps = (self.iteration - sample.modified) as f64;
+            let sigma = self.sigma * steps.sqrt();
+            // Box-Muller transform.
+            let u1: f64 = 1.0 - self.rng.gen_range(0.0, 1.0);
+            let u2: f64 = self.rng.gen_range(0.0, 1.0);
+            let normal = (-2.0 * u1.ln()).sqrt()
+                * (2.0 * std::f64::consts::PI * u2).cos();
+            sample.value += normal * sigma;
+            sample.value -= sample.value.floor();
+            if sample.value >= 1.0 {
+                // Rounding of tiny negative values.
+                sample.value = 0.0;
+            }
+        }
+        sample.modified = self.iteration;
+    }
+}
+
+impl Sampler for MetropolisSampler {
+    fn next(&mut self) -> f64 {
+        // New coordinates are uniform, as if drawn at the last large step
+        // (rejection sampling loops would never end with small steps from
+        // a constant).
+        if self.index >= self.samples.len() {
+            let value = self.rng.gen_range(0.0, 1.0);
+            self.samples.push(PrimarySample {
+                value,
+                modified: self.last_large_step,
+                ..PrimarySample::default()
+            });
+        }
+        let index = self.index;
+        self.mutate(index);
+        self.index += 1;
+
+        self.samples[index].value
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Sample generators for the pixels, Random (independent numbers) by
+ * default. The others are low-discrepancy: the points of the samples of a
+ * pixel cover the sample space evenly, dimension by dimension (position in
+ * the pixel, on the lens, bounce directions, ...), so pixels converge
+ * faster.
+ * * Stratified: every dimension split in as many strata as samples, with a
+ *   (jittered) sample in each of them, in a random order per dimension.
+ * * Halton: the Halton sequence (a prime base per dimension), Owen
+ *   scrambled per pixel.
+ * * Sobol: the Sobol (0,2)-sequence for each pair of dimensions, scrambled
+ *   and shuffled per pixel.
+ * * BlueNoise: the same (0,2)-sequences in every pixel, shifted by a
+ *   blue-noise mask, so that the errors of neighbouring pixels differ (the
+ *   noise looks finer).
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Sampling {
+    #[default]
+    Random,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+/**
+ * Sampler of the pixel (x, y), whose random numbers come from the stream of
+ * the seed, for the given number of samples.
+ */
+pub fn pixel_sampler(
+    sampling: Sampling,
+    seed: u64,
+    stream: u64,
+    x: u32,
+    y: u32,
+    samples: u32,
+) -> Box<dyn Sampler> {
+    match sampling {
+        Sampling::Random => Box::new(RandomSampler::seeded(seed, stream)),
+        _ => Box::new(LowDiscrepancySampler {
+            sampling,
+            seed,
+            scramble: mix(seed, stream),
+            x,
+            y,
+            samples,
+            sample: 0,
+            dimension: 0,
+            rng: Pcg64::seed_from_u64(mix(seed, stream)),
+        }),
+    }
+}
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71,
+    73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+/**
+ * See Sampling, dimensions past the ones of the sequence (Halton) are
+ * independent random numbers.
+ *
+ * Reference:
+ * * Pharr et al., "Physically Based Rendering", 4th edition, chapter 8.
+ * * Georgiev and Fajardo, "Blue-noise Dithered Sampling", 2016.
+ */
+pub struct LowDiscrepancySampler {
+    sampling: Sampling,
+    seed: u64,
+    scramble: u64,
+    x: u32,
+    y: u32,
+    samples: u32,
+    sample: u64,
+    dimension: u64,
+    rng: Pcg64,
+}
+
+impl LowDiscrepancySampler {
+    /**
+     * Index of the sample in the (first samples) points of the sequence,
+     * in an order given by the hash.
+     */
+    fn shuffle(&self, hash: u64) -> u64 {
+        if self.sample < self.samples as u64 {
+            permutation_element(self.sample as u32, self.samples, hash as u32)
+                as u64
+        } else {
+            self.sample
+        }
+    }
+}
+
+impl Sampler for LowDiscrepancySampler {
+    fn next(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        let value = match self.sampling {
+            Sampling::Stratified if self.sample < self.samples as u64 => {
+                let stratum = self.shuffle(mix(self.scramble, dimension));
+                (stratum as f64 + self.rng.gen_range(0.0, 1.0))
+                    / self.samples as f64
+            }
+            Sampling::Halton if dimension < PRIMES.len() as u64 => {
+                owen_radical_inverse(
+                    PRIMES[dimension as usize],
+                    self.sample,
+                    mix(self.scramble, dimension),
+                )
+            }
+            Sampling::Sobol => {
+                let hash = mix(self.scramble, dimension / 2);
+                let index = self.shuffle(hash);
+                let scramble = (hash >> (32 * (dimension % 2))) as u32;
+                zero_two(index, dimension % 2, scramble)
+            }
+            Sampling::BlueNoise => {
+                let hash = mix(self.seed, dimension / 2);
+                let point = zero_two(self.shuffle(hash), dimension % 2, 0);
+
+                let shift = mix(self.seed, !dimension);
+                let size = BLUE_NOISE_SIZE as u64;
+                let x = (self.x as u64 + shift % size) % size;
+                let y = (self.y as u64 + (shift >> 32) % size) % size;
+                let offset = blue_noise_mask()[(y * size + x) as usize];
+                (point + offset).fract()
+            }
+            _ => self.rng.gen_range(0.0, 1.0),
+        };
+        value.min(ONE_MINUS_EPSILON)
+    }
+
+    fn start_sample(&mut self, sample: u64) {
+        self.sample = sample;
+        self.dimension = 0;
+    }
+}
+
+/**
+ * Element i of a random permutation of [0, count) given by the hash, without
+ * building it (Kensler, "Correlated Multi-Jittered Sampling", 2013).
+ */
+fn permutation_element(mut i: u32, count: u32, hash: u32) -> u32 {
+    let mut w = count.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= hash;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= hash >> 16;
+        i ^= (i & w) >> 4;
+        i ^= hash >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= hash >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | hash >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < count {
+            break;
+        }
+    }
+    (i.wrapping_add(hash)) % count
+}
+
+/**
+ * Radical inverse of the index in the base (its digits mirrored around the
+ * decimal point), with every digit permuted by the hash of the digits
+ * before it (Owen scrambling).
+ */
+fn owen_radical_inverse(base: u64, mut index: u64, hash: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut inverse_base_m = 1.0;
+    let mut reversed: u64 = 0;
+    while 1.0 - inverse_base_m < 1.0 {
+        let next = index / base;
+        let digit = index - next * base;
+        let digit_hash = mix(hash ^ reversed, 0) as u32;
+        let digit =
+            permutation_element(digit as u32, base as u32, digit_hash) as u64;
+        reversed = reversed * base + digit;
+        inverse_base_m *= inverse_base;
+        index = next;
+    }
+
+    reversed as f64 * inverse_base_m
+}
+
+/**
+ * Component (0 or 1) of the point of the Sobol (0,2)-sequence, scrambled
+ * (xor of its bits, which keeps its stratification).
+ */
+fn zero_two(index: u64, component: u64, scramble: u32) -> f64 {
+    let index = index as u32;
+    let bits = if component == 0 {
+        index.reverse_bits() ^ scramble
+    } else {
+        let mut bits = scramble;
+        let mut v: u32 = 1 << 31;
+        let mut n = index;
+        while n != 0 {
+            if n & 1 != 0 {
+                bits ^= v;
+            }
+            n >>= 1;
+            v ^= v >> 1;
+        }
+        bits
+    };
+
+    bits as f64 / (1u64 << 32) as f64
+}
+
+const BLUE_NOISE_SIZE: usize = 32;
+
+/**
+ * Blue-noise mask: ranks of its pixels in [0, 1), tiled over the image. Made
+ * once with the void-and-cluster method: pixels are ranked by repeatedly
+ * taking the tightest cluster out of (or filling the largest void of) a
+ * pattern, measured by the (toroidal) Gaussian energy of its pixels.
+ *
+ * Reference:
+ * * Ulichney, "The void-and-cluster method for dither array generation",
+ *   1993.
+ */
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        let size = BLUE_NOISE_SIZE;
+        let count = size * size;
+        let sigma2 = 2.0 * 1.5 * 1.5;
+        let gaussian: Vec<f64> = (0..count)
+            .map(|delta| {
+                let (dx, dy) = (delta % size, delta / size);
+                let dx = dx.min(size - dx) as f64;
+                let dy = dy.min(size - dy) as f64;
+                (-(dx * dx + dy * dy) / sigma2).exp()
+            })
+            .collect();
+
+        struct Pattern<'a> {
+            size: usize,
+            gaussian: &'a [f64],
+            set: Vec<bool>,
+            energy: Vec<f64>,
+        }
+        impl<'a> Pattern<'a> {
+            fn toggle(&mut self, pixel: usize) {
+                self.set[pixel] = !self.set[pixel];
+                let sign = if self.set[pixel] { 1.0 } else { -1.0 };
+                let (x, y) = (pixel % self.size, pixel / self.size);
+                for (other, energy) in self.energy.iter_mut().enumerate() {
+                    let dx = (other % self.size + self.size - x) % self.size;
+                    let dy = (other / self.size + self.size - y) % self.size;
+                    *energy += sign * self.gaussian[dy * self.size + dx];
+                }
+            }
+            fn tightest_cluster(&self) -> usize {
+                self.extreme(true)
+            }
+            fn largest_void(&self) -> usize {
+                self.extreme(false)
+            }
+            fn extreme(&self, set: bool) -> usize {
+                let energy = |pixel: &usize| self.energy[*pixel];
+                let pixels =
+                    (0..self.set.len()).filter(|p| self.set[*p] == set);
+                let order = |a: &usize, b: &usize| {
+                    energy(a).partial_cmp(&energy(b)).unwrap()
+                };
+                if set {
+                    pixels.max_by(order).unwrap()
+                } else {
+                    pixels.min_by(order).unwrap()
+                }
+            }
+        }
+
+        // Initial pattern (a tenth of the pixels), its points moved from
+        // the tightest cluster to the largest void until it is even.
+        let mut pattern = Pattern {
+            size,
+            gaussian: &gaussian,
+            set: vec![false; count],
+            energy: vec![0.0; count],
+        };
+        let mut rng = Pcg64::seed_from_u64(0);
+        let mut initial = 0;
+        while initial < count / 10 {
+            let pixel = rng.gen_range(0, count);
+            if !pattern.set[pixel] {
+                pattern.toggle(pixel);
+                initial += 1;
+            }
+        }
+        loop {
+            let cluster = pattern.tightest_cluster();
+            pattern.toggle(cluster);
+            let void = pattern.largest_void();
+            pattern.toggle(void);
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut ranks = vec![0; count];
+        let (set, energy) = (pattern.set.clone(), pattern.energy.clone());
+        for rank in (0..initial).rev() {
+            let cluster = pattern.tightest_cluster();
+            pattern.toggle(cluster);
+            ranks[cluster] = rank;
+        }
+        pattern.set = set;
+        pattern.energy = energy;
+        for rank in initial..count {
+            let void = pattern.largest_void();
+            pattern.toggle(void);
+            ranks[void] = rank;
+        }
+
+        ranks
+            .iter()
+            .map(|rank| (*rank as f64 + 0.5) / count as f64)
+            .collect()
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Tests
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_sampler() {
+        struct Constant(f64);
+        impl Sampler for Constant {
+            fn next(&mut self) -> f64 {
+                self.0
+            }
+        }
+
+        with_sampler(Box::new(RandomSampler::seeded(0, 0)), || {
+            let value = with_sampler(Box::new(Constant(0.5)), || {
+                assert!(gen_range(-1.0, 3.0) == 1.0);
+                assert!(gen_index(4) == 2);
+                next_1d()
+            });
+            assert!(value == 0.5);
+
+            // The previous one is restored.
+            let values: Vec<f64> = (0..100).map(|_| next_1d()).collect();
+            assert!(values.iter().all(|v| *v >= 0.0 && *v < 1.0));
+            assert!(values.iter().any(|v| *v != values[0]));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "no sampler installed")]
+    fn unset_sampler() {
+        // Neither this thread nor e.g. a rayon worker has a sampler of its
+        // own: drawing from them is not reproducible.
+        next_1d();
+    }
+
+    #[test]
+    fn seeded_sampler() {
+        let draw = |seed, stream| {
+            let mut sampler = RandomSampler::seeded(seed, stream);
+            (0..10).map(|_| sampler.next()).collect::<Vec<f64>>()
+        };
+
+        assert!(draw(1, 2) == draw(1, 2));
+        assert!(draw(1, 2) != draw(1, 3));
+        assert!(draw(1, 2) != draw(2, 2));
+
+        let seeds = with_sampler(Box::new(RandomSampler::seeded(1, 2)), || {
+            (0..10).map(|_| next_seed()).collect::<Vec<u64>>()
+        });
+        assert!(seeds.iter().all(|seed| *seed < 1u64 << 53));
+        assert!(seeds.iter().any(|seed| *seed != seeds[0]));
+    }
+
+    #[test]
+    fn metropolis_sampler() {
+        let mut sampler = MetropolisSampler::new(7, 0.3, 0.01);
+        let start: Vec<f64> = (0..8).map(|_| sampler.next()).collect();
+        assert!(start.iter().all(|v| *v >= 0.0 && *v < 1.0));
+
+        // Rejected mutations go back to the same point.
+        for _ in 0..50 {
+            sampler.start_iteration();
+            let mutated: Vec<f64> = (0..8).map(|_| sampler.next()).collect();
+            assert!(mutated.iter().all(|v| *v >= 0.0 && *v < 1.0));
+            assert!(mutated != start);
+            sampler.reject();
+        }
+        sampler.start_iteration();
+        sampler.reject();
+        assert!(sampler.samples.iter().map(|s| s.value).eq(start.clone()));
+
+        // Accepted small steps stay close to the previous point.
+        let mut previous = start;
+        let mut small = 0;
+        for _ in 0..200 {
+            sampler.start_iteration();
+            let large = sampler.large_step;
+            let mutated: Vec<f64> = (0..8).map(|_| sampler.next()).collect();
+            if !large {
+                small += 1;
+                for (a, b) in mutated.iter().zip(previous.iter()) {
+                    let distance = (a - b).abs();
+                    assert!(distance.min(1.0 - distance) < 0.1);
+                }
+            }
+            sampler.accept();
+            previous = mutated;
+        }
+        assert!(small > 100);
+    }
+
+    #[test]
+    fn sampling_convergence() {
+        // Integral (1) of a smooth function over 4 dimensions, estimated in
+        // the pixels: low-discrepancy samples have a lower error than
+        // independent ones, for the same count.
+        let samples = 16;
+        let error = |sampling: Sampling| {
+            let mut error2 = 0.0;
+            for pixel in 0..64 {
+                let mut sampler =
+                    pixel_sampler(sampling, 3, pixel + 1, pixel as u32, 0, 16);
+                let mut estimate = 0.0;
+                for sample in 0..samples {
+                    sampler.start_sample(sample);
+                    let value: f64 = (0..4)
+                        .map(|_| {
+                            let u = sampler.next();
+                            assert!((0.0..1.0).contains(&u));
+                            2.0 * u
+                        })
+                        .product();
+                    estimate += value / samples as f64;
+                }
+                error2 += (estimate - 1.0) * (estimate - 1.0) / 64.0;
+            }
+            error2.sqrt()
+        };
+
+        let random = error(Sampling::Random);
+        for sampling in [
+            Sampling::Stratified,
+            Sampling::Halton,
+            Sampling::Sobol,
+            Sampling::BlueNoise,
+        ]
+        .iter()
+        {
+            let low_discrepancy = error(*sampling);
+            assert!(
+                low_discrepancy < 0.75 * random,
+                "{:?}: {} vs {} (random)",
+                sampling,
+                low_discrepancy,
+                random
+            );
+        }
+    }
+
+    #[test]
+    fn blue_noise_ranks() {
+        let mask = blue_noise_mask();
+        let mut ranks: Vec<usize> = mask
+            .iter()
+            .map(|value| (value * mask.len() as f64) as usize)
+            .collect();
+        ranks.sort();
+        assert!(ranks == (0..mask.len()).collect::<Vec<usize>>());
+    }
+}
@@ -3,25 +3,43 @@ use crate::raytracer::actor::Sphere;
 use crate::raytracer::camera::Camera;
 use crate::raytracer::canvas::Canvas;
 use crate::raytracer::common::Vec4;
+use crate::raytracer::integrator::BidirectionalPathTracer;
 use crate::raytracer::material::Dielectric;
 use crate::raytracer::material::Lambertian;
+use crate::raytracer::material::MaterialLibrary;
 use crate::raytracer::material::Metal;
+use crate::raytracer::material::Primary;
 use crate::raytracer::material::Shading;
 use ndarray::arr1;
 use rand::prelude::*;
 use rand_pcg::Pcg64;
 
-pub fn random_book_cover() -> Vec<Box<dyn RayTraceable>> {
+pub fn random_book_cover(
+    materials: &mut MaterialLibrary,
+) -> Vec<Box<dyn RayTraceable>> {
     let mut actors: Vec<Box<dyn RayTraceable>> = Vec::with_capacity(1000);
 
+    // Shared by all the glass spheres.
+    let glass = materials.add(
+        "glass",
+        Box::new(Dielectric::new(
+            arr1(&[1.0, 1.0, 1.0, 0.0]),
+            Shading::COLOR,
+            1.5,
+        )),
+    );
+
     // World orbe
     actors.push(Box::new(Sphere {
         center: arr1(&[0.0, -1000.0, 0.0, 1.0]),
         radius: 1000.0,
-        material: Box::new(Lambertian::new(
-            arr1(&[0.5, 0.5, 0.5, 1.0]),
-            Shading::COLOR,
-        )),
+        material: materials.add(
+            "ground",
+            Box::new(Lambertian::new(
+                arr1(&[0.5, 0.5, 0.5, 1.0]),
+                Shading::COLOR,
+            )),
+        ),
     }) as Box<dyn RayTraceable>);
 
     let mut rng = Pcg64::seed_from_u64(1 as u64);
@@ -44,42 +62,44 @@ pub fn random_book_cover() -> Vec<Box<dyn RayTraceable>> {
                     actors.push(Box::new(Sphere {
                         center,
                         radius,
-                        material: Box::new(Lambertian::new(
-                            arr1(&[
-                                rng.gen::<f64>() * rng.gen::<f64>(),
-                                rng.gen::<f64>() * rng.gen::<f64>(),
-                                rng.gen::<f64>() * rng.gen::<f64>(),
-                                1.0,
-                            ]),
-                            Shading::COLOR,
-                        )),
+                        material: materials.add(
+                            &format!("diffuse_{}_{}", a, b),
+                            Box::new(Lambertian::new(
+                                arr1(&[
+                                    rng.gen::<f64>() * rng.gen::<f64>(),
+                                    rng.gen::<f64>() * rng.gen::<f64>(),
+                                    rng.gen::<f64>() * rng.gen::<f64>(),
+                                    1.0,
+                                ]),
+                                Shading::COLOR,
+                            )),
+                        ),
                     })
                         as Box<dyn RayTraceable>);
                 } else if choose_mat < 0.95 {
                     actors.push(Box::new(Sphere {
                         center,
                         radius,
-                        material: Box::new(Metal::new(
-                            arr1(&[
-                                0.5 * (1.0 + rng.gen::<f64>()),
-                                0.5 * (1.0 + rng.gen::<f64>()),
-                                0.5 * (1.0 + rng.gen::<f64>()),
-                                1.0,
-                            ]),
-                            Shading::COLOR,
-                            0.5 * rng.gen::<f64>(),
-                        )),
+                        material: materials.add(
+                            &format!("metal_{}_{}", a, b),
+                            Box::new(Metal::new(
+                                arr1(&[
+                                    0.5 * (1.0 + rng.gen::<f64>()),
+                                    0.5 * (1.0 + rng.gen::<f64>()),
+                                    0.5 * (1.0 + rng.gen::<f64>()),
+                                    1.0,
+                                ]),
+                                Shading::COLOR,
+                                0.5 * rng.gen::<f64>(),
+                            )),
+                        ),
                     })
                         as Box<dyn RayTraceable>);
                 } else {
                     actors.push(Box::new(Sphere {
                         center,
                         radius,
-                        material: Box::new(Dielectric::new(
-                            arr1(&[1.0, 1.0, 1.0, 0.0]),
-                            Shading::COLOR,
-                            1.5,
-                        )),
+                        material: glass,
                     })
                         as Box<dyn RayTraceable>);
                 }
@@ -91,65 +111,153 @@ pub fn random_book_cover() -> Vec<Box<dyn RayTraceable>> {
     actors.push(Box::new(Sphere {
         center: arr1(&[0.0, 1.0, 0.0, 1.0]),
         radius: 1.0,
-        material: Box::new(Dielectric::new(
-            arr1(&[1.0, 1.0, 1.0, 0.0]),
-            Shading::COLOR,
-            1.5,
-        )),
+        material: glass,
     }) as Box<dyn RayTraceable>);
 
     actors.push(Box::new(Sphere {
         center: arr1(&[-4.0, 1.0, 0.0, 1.0]),
         radius: 1.0,
-        material: Box::new(Lambertian::new(
-            arr1(&[0.4, 0.2, 0.1, 1.0]),
-            Shading::COLOR,
-        )),
+        material: materials.add(
+            "diffuse",
+            Box::new(Lambertian::new(
+                arr1(&[0.4, 0.2, 0.1, 1.0]),
+                Shading::COLOR,
+            )),
+        ),
     }) as Box<dyn RayTraceable>);
 
     actors.push(Box::new(Sphere {
         center: arr1(&[4.0, 1.0, 0.0, 1.0]),
         radius: 1.0,
-        material: Box::new(Metal::new(
-            arr1(&[0.7, 0.6, 0.5, 1.0]),
-            Shading::COLOR,
-            0.0,
-        )),
+        material: materials.add(
+            "metal",
+            Box::new(Metal::new(
+                arr1(&[0.7, 0.6, 0.5, 1.0]),
+                Shading::COLOR,
+                0.0,
+            )),
+        ),
     }) as Box<dyn RayTraceable>);
 
     actors
 }
 
-pub fn two_spheres_normals() -> Vec<Box<dyn RayTraceable>> {
+pub fn two_spheres_normals(
+    materials: &mut MaterialLibrary,
+) -> Vec<Box<dyn RayTraceable>> {
     let mut actors: Vec<Box<dyn RayTraceable>> = Vec::with_capacity(1000);
     actors.push(Box::new(Sphere {
         center: arr1(&[0.0, 0.0, -1.0, 1.0]),
         radius: 0.5,
-        material: Box::new(Lambertian::new(
-            arr1(&[1.0, 0.0, 0.0, 1.0]),
-            Shading::NORMALS,
-        )),
+        material: materials.add(
+            "sphere",
+            Box::new(Lambertian::new(
+                arr1(&[1.0, 0.0, 0.0, 1.0]),
+                Shading::NORMALS,
+            )),
+        ),
     }) as Box<dyn RayTraceable>);
 
     actors.push(Box::new(Sphere {
         center: arr1(&[0.0, -100.5, -1.0, 1.0]),
         radius: 100.0,
-        material: Box::new(Lambertian::new(
-            arr1(&[0.0, 1.0, 0.0, 1.0]),
-            Shading::NORMALS,
-        )),
+        material: materials.add(
+            "ground",
+            Box::new(Lambertian::new(
+                arr1(&[0.0, 1.0, 0.0, 1.0]),
+                Shading::NORMALS,
+            )),
+        ),
     }) as Box<dyn RayTraceable>);
 
     actors
 }
 
+/**
+ * Closed box of (very large) spheres, red on the left and green on the
+ * right, lit by a small spherical light below the ceiling. The box spans
+ * [-1, 1] on every axis, and the camera is meant to be inside.
+ */
+pub fn cornell_box(
+    materials: &mut MaterialLibrary,
+) -> Vec<Box<dyn RayTraceable>> {
+    let mut actors: Vec<Box<dyn RayTraceable>> = Vec::with_capacity(10);
+    let white = materials.add(
+        "white",
+        Box::new(Lambertian::new(
+            arr1(&[0.73, 0.73, 0.73, 1.0]),
+            Shading::COLOR,
+        )),
+    );
+    let red = materials.add(
+        "red",
+        Box::new(Lambertian::new(
+            arr1(&[0.65, 0.05, 0.05, 1.0]),
+            Shading::COLOR,
+        )),
+    );
+    let green = materials.add(
+        "green",
+        Box::new(Lambertian::new(
+            arr1(&[0.12, 0.45, 0.15, 1.0]),
+            Shading::COLOR,
+        )),
+    );
+    let light = materials.add(
+        "light",
+        Box::new(Primary::new(arr1(&[15.0, 15.0, 15.0, 1.0]), Shading::COLOR)),
+    );
+
+    // Walls
+    let walls = [
+        ([-1001.0, 0.0, 0.0], red),
+        ([1001.0, 0.0, 0.0], green),
+        ([0.0, -1001.0, 0.0], white),
+        ([0.0, 1001.0, 0.0], white),
+        ([0.0, 0.0, -1001.0], white),
+        ([0.0, 0.0, 1001.0], white),
+    ];
+    for (center, material) in walls.iter() {
+        actors.push(Box::new(Sphere::new(
+            arr1(&[center[0], center[1], center[2], 1.0]),
+            1000.0,
+            *material,
+        )) as Box<dyn RayTraceable>);
+    }
+
+    actors.push(Box::new(Sphere::new(
+        arr1(&[-0.4, -0.65, -0.3, 1.0]),
+        0.35,
+        white,
+    )) as Box<dyn RayTraceable>);
+    actors.push(Box::new(Sphere::new(
+        arr1(&[0.45, -0.7, 0.2, 1.0]),
+        0.3,
+        materials.add(
+            "metal",
+            Box::new(Metal::new(
+                arr1(&[0.8, 0.8, 0.8, 1.0]),
+                Shading::COLOR,
+                0.0,
+            )),
+        ),
+    )) as Box<dyn RayTraceable>);
+    actors.push(
+        Box::new(Sphere::new(arr1(&[0.0, 0.8, -0.2, 1.0]), 0.12, light))
+            as Box<dyn RayTraceable>,
+    );
+
+    actors
+}
+
 pub fn get_renderer(scene_id: u32) -> Box<Canvas> {
     let dims: [u32; 2] = [200, 133];
     let actors: Vec<Box<dyn RayTraceable>>;
+    let mut materials = MaterialLibrary::new();
     let camera: Camera;
     match scene_id {
         0 => {
-            actors = random_book_cover();
+            actors = random_book_cover(&mut materials);
             camera = Camera::new(
                 20.0,
                 dims[0],
@@ -160,8 +268,20 @@ pub fn get_renderer(scene_id: u32) -> Box<Canvas> {
                 0.2,
             );
         }
+        3 => {
+            actors = cornell_box(&mut materials);
+            camera = Camera::new(
+                70.0,
+                dims[0],
+                dims[1],
+                arr1(&[0.0, 0.0, 0.95, 1.0]),
+                arr1(&[0.0, 0.0, 0.0, 1.0]),
+                arr1(&[0.0, 1.0, 0.0, 0.0]),
+                0.0,
+            );
+        }
         _ => {
-            actors = two_spheres_normals();
+            actors = two_spheres_normals(&mut materials);
             camera = Camera::new(
                 90.0,
                 dims[0],
@@ -174,5 +294,10 @@ pub fn get_renderer(scene_id: u32) -> Box<Canvas> {
         }
     }
 
-    Box::new(Canvas::new(dims[0], dims[1], actors, 2, camera))
+    let mut canvas =
+        Canvas::new(dims[0], dims[1], actors, materials, 2, camera);
+    if scene_id == 3 {
+        canvas.integrator = Box::new(BidirectionalPathTracer::new());
+    }
+    Box::new(canvas)
 }
@@ -0,0 +1,232 @@
+use ndarray::{arr1, Array1};
+
+/**
+ * Visible range (in nm) sampled by the spectral mode.
+ */
+pub const WAVELENGTH_MIN: f64 = 380.0;
+pub const WAVELENGTH_MAX: f64 = 720.0;
+
+/**
+ * Representative wavelengths (in nm) of the (r, g, b) channels, used by
+ * wavelength dependent effects (e.g. thin-film interference) outside
+ * spectral mode.
+ */
+pub const RGB_WAVELENGTHS: [f64; 3] = [630.0, 532.0, 465.0];
+
+/**
+ * Integral of the y color matching function over the sampled range, used
+ * to normalize the estimates so that luminance (Y) is in [0.0, 1.0].
+ */
+const CIE_Y_INTEGRAL: f64 = 106.911868;
+
+/**
+ * Linear sRGB of the equal-energy spectrum (CIE illuminant E). Spectra are
+ * white balanced with it, so that a constant spectrum of 1.0 maps back to
+ * (1.0, 1.0, 1.0), as it does in RGB mode.
+ */
+const WHITE_RGB: [f64; 3] = [1.200606, 0.949638, 0.907908];
+
+/**
+ * Smits' basis spectra, 10 bins evenly spaced over the sampled range.
+ *
+ * Reference:
+ * * Smits, "An RGB to Spectrum Conversion for Reflectances", 1999.
+ */
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000,
+    1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000,
+    0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000,
+    0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685,
+    0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149,
+    1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000,
+    0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483,
+    0.0496,
+];
+
+/**
+ * Maps a uniform random number in [0.0, 1.0) to a wavelength in the
+ * sampled range. Returns the wavelength (nm) and its pdf.
+ */
+pub fn sample_wavelength(u: f64) -> (f64, f64) {
+    let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+
+    (WAVELENGTH_MIN + u * range, 1.0 / range)
+}
+
+/**
+ * Piecewise Gaussian used by the analytic color matching functions.
+ */
+fn gaussian(wavelength: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let sigma = if wavelength < mu {
+        sigma_low
+    } else {
+        sigma_high
+    };
+    let t = (wavelength - mu) / sigma;
+
+    (-0.5 * t * t).exp()
+}
+
+/**
+ * CIE 1931 2-degree color matching functions (x, y, z), evaluated through
+ * their multi-lobe Gaussian fit.
+ *
+ * Reference:
+ * * Wyman, Sloan, Shirley, "Simple Analytic Approximations to the CIE XYZ
+ *   Color Matching Functions", JCGT 2013.
+ */
+pub fn color_matching(wavelength: f64) -> [f64; 3] {
+    let x = 1.056 * gaussian(wavelength, 599.8, 37.9, 31.0)
+        + 0.362 * gaussian(wavelength, 442.0, 16.0, 26.7)
+        - 0.065 * gaussian(wavelength, 501.1, 20.4, 26.2);
+    let y = 0.821 * gaussian(wavelength, 568.8, 46.9, 40.5)
+        + 0.286 * gaussian(wavelength, 530.9, 16.3, 31.1);
+    let z = 1.217 * gaussian(wavelength, 437.0, 11.8, 36.0)
+        + 0.681 * gaussian(wavelength, 459.0, 26.0, 13.8);
+
+    [x, y, z]
+}
+
+/**
+ * Monte Carlo estimate of the XYZ color of a single radiance sample taken
+ * at the given wavelength (with the pdf it was sampled with).
+ */
+pub fn to_xyz(radiance: f64, wavelength: f64, pdf: f64) -> Array1<f64> {
+    let cmf = color_matching(wavelength);
+    let scale = radiance / (pdf * CIE_Y_INTEGRAL);
+
+    arr1(&[cmf[0] * scale, cmf[1] * scale, cmf[2] * scale, 1.0])
+}
+
+/**
+ * XYZ to (white balanced) linear sRGB, the alpha channel is kept as is.
+ */
+pub fn xyz_to_rgb(xyz: &Array1<f64>) -> Array1<f64> {
+    let (x, y, z) = (xyz[0], xyz[1], xyz[2]);
+
+    arr1(&[
+        (3.2404542 * x - 1.5371385 * y - 0.4985314 * z) / WHITE_RGB[0],
+        (-0.969266 * x + 1.8760108 * y + 0.041556 * z) / WHITE_RGB[1],
+        (0.0556434 * x - 0.2040259 * y + 1.0572252 * z) / WHITE_RGB[2],
+        xyz[3],
+    ])
+}
+
+/**
+ * Luminance (Y) of a linear sRGB color.
+ */
+pub fn luminance(rgb: &Array1<f64>) -> f64 {
+    0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]
+}
+
+/**
+ * Value of an RGB color (reflectance) at the given wavelength, following
+ * Smits' upsampling: the color is decomposed into white plus (at most) one
+ * of cyan, magenta or yellow plus one of red, green or blue.
+ */
+pub fn rgb_to_spectrum(rgb: &Array1<f64>, wavelength: f64) -> f64 {
+    let bin = ((wavelength - WAVELENGTH_MIN)
+        / (WAVELENGTH_MAX - WAVELENGTH_MIN)
+        * 10.0)
+        .clamp(0.0, 9.0) as usize;
+    let (r, g, b) = (rgb[0], rgb[1], rgb[2]);
+
+    let value = if r <= g && r <= b {
+        r * SMITS_WHITE[bin]
+            + if g <= b {
+                (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+            } else {
+                (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+            }
+    } else if g <= r && g <= b {
+        g * SMITS_WHITE[bin]
+            + if r <= b {
+                (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+            } else {
+                (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+            }
+    } else {
+        b * SMITS_WHITE[bin]
+            + if r <= g {
+                (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+            } else {
+                (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+            }
+    };
+
+    value.max(0.0)
+}
+
+/**
+ * Replaces the (r, g, b) channels of a color by its spectral value at the
+ * given wavelength, so that it can be multiplied as usual along the path.
+ * The alpha channel is kept as is.
+ */
+pub fn at_wavelength(rgb: &Array1<f64>, wavelength: f64) -> Array1<f64> {
+    let value = rgb_to_spectrum(rgb, wavelength);
+
+    arr1(&[value, value, value, rgb[3]])
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Tests
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * Integrates the spectrum of an RGB color back to RGB.
+     */
+    fn round_trip(rgb: Array1<f64>) -> Array1<f64> {
+        let steps = 3400;
+        let mut xyz = arr1(&[0.0, 0.0, 0.0, 0.0]);
+        for step in 0..steps {
+            let u = (step as f64 + 0.5) / steps as f64;
+            let (wavelength, pdf) = sample_wavelength(u);
+            xyz = xyz
+                + to_xyz(rgb_to_spectrum(&rgb, wavelength), wavelength, pdf);
+        }
+
+        xyz_to_rgb(&(xyz / steps as f64))
+    }
+
+    #[test]
+    fn spectrum_white_round_trip() {
+        let rgb = round_trip(arr1(&[1.0, 1.0, 1.0, 1.0]));
+        for channel in 0..3 {
+            assert!((rgb[channel] - 1.0).abs() < 0.01);
+        }
+        assert!((rgb[3] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn spectrum_primaries_round_trip() {
+        let red = round_trip(arr1(&[1.0, 0.0, 0.0, 1.0]));
+        assert!(red[0] > 0.8 && red[1] < 0.2 && red[2] < 0.2);
+
+        let green = round_trip(arr1(&[0.0, 1.0, 0.0, 1.0]));
+        assert!(green[1] > 0.8 && green[0] < 0.2 && green[2] < 0.2);
+
+        let blue = round_trip(arr1(&[0.0, 0.0, 1.0, 1.0]));
+        assert!(blue[2] > 0.8 && blue[0] < 0.2 && blue[1] < 0.2);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::raytracer::image::Image;
+use ndarray::{arr1, Array1};
+use std::path::Path;
+
+extern crate image;
+use image::open;
+
+/**
+ * Textures map surface coordinates (u, v) in [0.0, 1.0] (and/or the hit
+ * point, for solid textures) to a value. Colors are RGBA in [0.0, 1.0],
+ * scalar textures (e.g. height maps) store their value in every channel.
+ */
+pub trait Texture: Sync + Send {
+    fn value(&self, u: f64, v: f64, point: &Array1<f64>) -> Array1<f64>;
+
+    fn clone_box(&self) -> Box<dyn Texture>;
+}
+
+impl Clone for Box<dyn Texture> {
+    fn clone(&self) -> Box<dyn Texture> {
+        self.clone_box()
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Same value everywhere.
+ */
+#[derive(Clone)]
+pub struct ConstantTexture {
+    pub color: Array1<f64>,
+}
+
+impl ConstantTexture {
+    pub fn new(color: Array1<f64>) -> ConstantTexture {
+        ConstantTexture { color }
+    }
+}
+
+impl Texture for ConstantTexture {
+    fn value(&self, _u: f64, _v: f64, _point: &Array1<f64>) -> Array1<f64> {
+        self.color.clone()
+    }
+
+    fn clone_box(&self) -> Box<dyn Texture> {
+        Box::new((*self).clone())
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Checker board in (u, v) space, with the given number of squares along
+ * each direction.
+ */
+#[derive(Clone)]
+pub struct CheckerTexture {
+    pub even: Array1<f64>,
+    pub odd: Array1<f64>,
+    pub squares: f64,
+}
+
+impl CheckerTexture {
+    pub fn new(
+        even: Array1<f64>,
+        odd: Array1<f64>,
+        squares: f64,
+    ) -> CheckerTexture {
+        CheckerTexture { even, odd, squares }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, _point: &Array1<f64>) -> Array1<f64> {
+        let parity = (u * self.squares).floor() + (v * self.squares).floor();
+
+        if parity as i64 % 2 == 0 {
+            self.even.clone()
+        } else {
+            self.odd.clone()
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Texture> {
+        Box::new((*self).clone())
+    }
+}
+
+// ----------------------------------------------------------------------------
+/**
+ * Texture backed by an image (nearest texel lookup, wraps around). The
+ * texel values are used as they are stored (no gamma decoding), which is
+ * what normal and height maps expect.
+ */
+#[derive(Clone)]
+pub struct ImageTexture {
+    pub image: Image,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> ImageTexture {
+        ImageTexture { image }
+    }
+
+    pub fn open(path: &Path) -> Result<ImageTexture, String> {
+        match open(path) {
+            Ok(image) => {
+                let rgba = image.to_rgba();
+                Ok(ImageTexture::new(Image::from_vec(
+                    rgba.width(),
+                    rgba.height(),
+                    4,
+                    rgba.to_vec(),
+                )))
+            }
+            Err(error) => Err(error.to_string()),
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: &Array1<f64>) -> Array1<f64> {
+        let width = self.image.width as f64;
+        let height = self.image.height as f64;
+
+        // Image rows go top to bottom, v goes bottom to top.
+        let x = ((u - u.floor()) * width).min(width - 1.0) as u32;
+        let y = ((1.0 - (v - v.floor())) * height).min(height - 1.0) as u32;
+
+        arr1(&[
+            self.image.get_value(x, y, 0) as f64 / 255.0,
+            self.image.get_value(x, y, 1) as f64 / 255.0,
+            self.image.get_value(x, y, 2) as f64 / 255.0,
+            self.image.get_value(x, y, 3) as f64 / 255.0,
+        ])
+    }
+
+    fn clone_box(&self) -> Box<dyn Texture> {
+        Box::new((*self).clone())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Tests
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_texture() {
+        let black = arr1(&[0.0, 0.0, 0.0, 1.0]);
+        let white = arr1(&[1.0, 1.0, 1.0, 1.0]);
+        let checker = CheckerTexture::new(black.clone(), white.clone(), 4.0);
+        let point = arr1(&[0.0, 0.0, 0.0, 1.0]);
+
+        assert!(checker.value(0.1, 0.1, &point) == black);
+        assert!(checker.value(0.3, 0.1, &point) == white);
+        assert!(checker.value(0.3, 0.3, &point) == black);
+    }
+
+    #[test]
+    fn image_texture() {
+        // 2x2 image, top row red / green, bottom row blue / white.
+        let image = Image::from_vec(
+            2,
+            2,
+            4,
+            vec![
+                255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255,
+                255,
+            ],
+        );
+        let texture = ImageTexture::new(image);
+        let point = arr1(&[0.0, 0.0, 0.0, 1.0]);
+
+        assert!(
+            texture.value(0.25, 0.75, &point) == arr1(&[1.0, 0.0, 0.0, 1.0])
+        );
+        assert!(
+            texture.value(0.75, 0.75, &point) == arr1(&[0.0, 1.0, 0.0, 1.0])
+        );
+        assert!(
+            texture.value(0.25, 0.25, &point) == arr1(&[0.0, 0.0, 1.0, 1.0])
+        );
+        assert!(texture.value(1.0, 0.0, &point) == arr1(&[0.0, 0.0, 1.0, 1.0]));
+    }
+}
@@ -1,6 +1,9 @@
 extern crate rendering;
 
 use rendering::raytracer::canvas::Canvas;
+use rendering::raytracer::canvas::RenderOptions;
+use rendering::raytracer::canvas::Tile;
+use rendering::raytracer::image::Image;
 use rendering::raytracer::scenes;
 use wasm_bindgen::prelude::*;
 use web_sys::console;
@@ -40,16 +43,74 @@ impl Renderer {
 
         console::log_1(&"Calling render_scene()".into());
         self.canvas.render_scene();
-        let frame = self.canvas.grab_frame();
-
-        let buf: Vec<u8> = frame
-            .data
-            .iter()
-            .flat_map(|pixel| pixel.data.iter())
-            .cloned()
-            .collect();
-        ByteStream::new(&buf, frame.width, frame.height)
+        self.frame()
     }
+
+    /**
+     * Adds samples to the image rendered so far and returns the current
+     * estimate, to show a preview that improves with every call.
+     */
+    pub fn render_progressive(&mut self, samples: u32) -> ByteStream {
+        set_panic_hook();
+
+        self.canvas.render_progressive(samples);
+        self.frame()
+    }
+
+    /**
+     * Renders only the pixels of the rectangle at (x, y), the others keep
+     * what was rendered before. Returns either the whole frame or just the
+     * rectangle (cropped), or an error if the rectangle is empty or not
+     * within the frame.
+     */
+    pub fn render_crop(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        cropped: bool,
+    ) -> Result<ByteStream, JsValue> {
+        set_panic_hook();
+
+        let crop = Tile {
+            x,
+            y,
+            width,
+            height,
+        };
+        self.canvas
+            .check_crop(&crop)
+            .map_err(|message| JsValue::from_str(&message))?;
+        self.canvas.render_scene_with(&RenderOptions {
+            crop: Some(crop),
+            ..RenderOptions::default()
+        });
+        if cropped {
+            Ok(stream(self.canvas.grab_crop(&crop)))
+        } else {
+            Ok(self.frame())
+        }
+    }
+
+    /** Starts the progressive rendering over. */
+    pub fn reset(&mut self) {
+        self.canvas.reset_progressive();
+    }
+
+    fn frame(&self) -> ByteStream {
+        stream(self.canvas.grab_frame())
+    }
+}
+
+fn stream(frame: Image) -> ByteStream {
+    let buf: Vec<u8> = frame
+        .data
+        .iter()
+        .flat_map(|pixel| pixel.data.iter())
+        .cloned()
+        .collect();
+    ByteStream::new(&buf, frame.width, frame.height)
 }
 
 #[wasm_bindgen]
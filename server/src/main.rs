@@ -3,29 +3,41 @@ extern crate rendering;
 
 use actix_cors::Cors;
 use actix_web::middleware::Logger;
-use actix_web::{get, App, HttpResponse, HttpServer, Responder};
+use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
 use env_logger::Env;
+use std::sync::Mutex;
 
 pub mod renderer;
+use crate::renderer::create_renderer;
+use crate::renderer::render_crop;
 use crate::renderer::render_frame;
+use crate::renderer::render_progressive;
+use crate::renderer::reset_progressive;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info"))
         .init();
 
-    HttpServer::new(|| {
+    // Progressive renders accumulate on one renderer, shared by the workers.
+    let progressive = web::Data::new(Mutex::new(create_renderer(0)));
+
+    HttpServer::new(move || {
         let cors = Cors::default()
             .allowed_origin("http://localhost:3000")
-            .allowed_methods(vec!["GET"])
+            .allowed_methods(vec!["GET", "POST"])
             .allowed_headers(vec!["*"])
             .max_age(3600);
 
         App::new()
             .wrap(Logger::default())
             .wrap(cors)
+            .app_data(progressive.clone())
             .service(health)
             .service(render_frame)
+            .service(render_crop)
+            .service(reset_progressive)
+            .service(render_progressive)
     })
     .bind(("0.0.0.0", 8082))?
     .run()
@@ -1,6 +1,10 @@
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, post, web, HttpResponse, Responder};
+use std::sync::{Mutex, MutexGuard};
 
 use rendering::raytracer::canvas::Canvas;
+use rendering::raytracer::canvas::RenderOptions;
+use rendering::raytracer::canvas::Tile;
+use rendering::raytracer::image::Image;
 use rendering::raytracer::scenes;
 
 pub struct Renderer {
@@ -18,6 +22,111 @@ async fn render_frame() -> impl Responder {
     renderer.canvas.render_scene();
     let image = renderer.canvas.grab_frame();
 
+    png_response(image)
+}
+
+/**
+ * Renders only the rectangle at (x, y) of the frame, and returns it
+ * cropped. The rectangle must be non-empty and within the frame.
+ */
+#[get("/api/v1/render/{x}/{y}/{width}/{height}")]
+async fn render_crop(crop: web::Path<(u32, u32, u32, u32)>) -> impl Responder {
+    let (x, y, width, height) = crop.into_inner();
+    let crop = Tile {
+        x,
+        y,
+        width,
+        height,
+    };
+
+    let mut renderer = create_renderer(0);
+    if let Err(message) = renderer.canvas.check_crop(&crop) {
+        return HttpResponse::BadRequest().body(message);
+    }
+    renderer.canvas.render_scene_with(&RenderOptions {
+        crop: Some(crop),
+        ..RenderOptions::default()
+    });
+    let image = renderer.canvas.grab_crop(&crop);
+
+    png_response(image)
+}
+
+/** Most samples a progressive request can add (it blocks a thread). */
+const MAX_PROGRESSIVE_SAMPLES: u32 = 64;
+
+/**
+ * Adds samples to the frame rendered so far by the shared renderer and
+ * returns the current estimate, a preview that improves with every call.
+ */
+#[get("/api/v1/render/progressive/{samples}")]
+async fn render_progressive(
+    renderer: web::Data<Mutex<Renderer>>,
+    samples: web::Path<u32>,
+) -> impl Responder {
+    let samples = samples.into_inner();
+    if samples == 0 || samples > MAX_PROGRESSIVE_SAMPLES {
+        return HttpResponse::BadRequest().body(format!(
+            "Samples must be within 1 and {}",
+            MAX_PROGRESSIVE_SAMPLES
+        ));
+    }
+
+    // Rendering (and waiting for the lock, held while rendering) blocks, so
+    // it runs in the thread pool for blocking work instead of the workers
+    // serving requests.
+    let image = web::block(move || {
+        let mut renderer = lock(&renderer);
+        renderer.canvas.render_progressive(samples);
+        renderer.canvas.grab_frame()
+    })
+    .await;
+
+    match image {
+        Ok(image) => png_response(image),
+        Err(error) => {
+            HttpResponse::InternalServerError().body(error.to_string())
+        }
+    }
+}
+
+/**
+ * Starts the progressive rendering of the shared renderer over (once the
+ * render in progress, if any, is done).
+ */
+#[post("/api/v1/render/progressive/reset")]
+async fn reset_progressive(
+    renderer: web::Data<Mutex<Renderer>>,
+) -> impl Responder {
+    let reset =
+        web::block(move || lock(&renderer).canvas.reset_progressive()).await;
+
+    match reset {
+        Ok(()) => HttpResponse::Ok().body("Ok"),
+        Err(error) => {
+            HttpResponse::InternalServerError().body(error.to_string())
+        }
+    }
+}
+
+/**
+ * Locks the shared renderer. A render that panicked poisons the lock, and
+ * may have left samples of the pass it did not finish: the renderer starts
+ * over instead of failing every request from then on.
+ */
+fn lock(renderer: &Mutex<Renderer>) -> MutexGuard<Renderer> {
+    match renderer.lock() {
+        Ok(renderer) => renderer,
+        Err(poisoned) => {
+            renderer.clear_poison();
+            let mut renderer = poisoned.into_inner();
+            renderer.canvas.reset_progressive();
+            renderer
+        }
+    }
+}
+
+fn png_response(image: Image) -> HttpResponse {
     let mut buffer: Vec<u8> = Vec::new();
 
     let buf: Vec<u8> = image